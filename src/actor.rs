@@ -0,0 +1,236 @@
+//! A virtual machine running on its own thread, for use from async code.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, mpsc},
+    task::{Context, Poll, Waker},
+    thread::JoinHandle,
+};
+
+use crate::{
+    InterruptHandle, Wren,
+    error::Error,
+    value::{Handle, OwnedValue},
+};
+
+type Command<U, M, W> = Box<dyn FnOnce(&mut Wren<U, M, W>) + Send>;
+
+/// A virtual machine which lives on a dedicated thread, and is driven by
+/// requests sent from other threads.
+///
+/// Each request is queued, and run on the virtual machine in the order it was
+/// made. Requests return a [`Reply`], a future which resolves once the request
+/// has been handled, so waiting on a script never blocks an async executor.
+///
+/// Dropping an `AsyncWren` finishes the requests already queued, then stops
+/// the thread and frees the virtual machine.
+pub struct AsyncWren<U, M, W> {
+    sender: Option<mpsc::Sender<Command<U, M, W>>>,
+    thread: Option<JoinHandle<()>>,
+    interrupt: InterruptHandle,
+}
+
+impl<U, M, W> AsyncWren<U, M, W>
+where
+    U: Send + 'static,
+    M: Send + 'static,
+    W: Send + 'static,
+{
+    /// Moves `wren` onto a new thread.
+    pub fn spawn(wren: Wren<U, M, W>) -> AsyncWren<U, M, W> {
+        let interrupt = wren.interrupt_handle();
+
+        let (sender, receiver) = mpsc::channel::<Command<U, M, W>>();
+
+        let thread = std::thread::Builder::new()
+            .name("wrenlet".to_owned())
+            .spawn(move || {
+                let mut wren = wren;
+
+                for command in receiver {
+                    command(&mut wren);
+                }
+            })
+            .expect("failed to spawn the virtual machine thread");
+
+        AsyncWren {
+            sender: Some(sender),
+            thread: Some(thread),
+            interrupt,
+        }
+    }
+
+    /// Runs `f` with the virtual machine, on its thread.
+    pub fn execute<T, F>(&self, f: F) -> Reply<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Wren<U, M, W>) -> Result<T, Error> + Send + 'static,
+    {
+        let (completer, reply) = Reply::new();
+
+        let command: Command<U, M, W> = Box::new(move |wren| completer.complete(f(wren)));
+
+        // If the thread has stopped, the command is dropped along with its
+        // completer, and the reply resolves to `Error::Disconnected`.
+        let _ = self.sender().send(command);
+
+        reply
+    }
+
+    /// Interprets the given `source` as Wren code in the context of the given
+    /// `module`.
+    ///
+    /// See [`Wren::interpret`].
+    pub fn interpret(&self, module: &str, source: &str) -> Reply<()> {
+        let module = module.to_owned();
+        let source = source.to_owned();
+
+        self.execute(move |wren| wren.interpret(&module, &source))
+    }
+
+    /// Looks up the top level variable in `module` called `name`.
+    ///
+    /// See [`Wren::get_variable`].
+    pub fn get_variable(&self, module: &str, name: &str) -> Reply<OwnedValue> {
+        let module = module.to_owned();
+        let name = name.to_owned();
+
+        self.execute(move |wren| wren.get_variable(&module, &name))
+    }
+
+    /// Calls the method with the given `signature` on the top level variable
+    /// `variable` in `module`.
+    ///
+    /// See [`Wren::call`].
+    pub fn call_method(
+        &self,
+        module: &str,
+        variable: &str,
+        signature: &str,
+        args: Vec<OwnedValue>,
+    ) -> Reply<OwnedValue> {
+        let module = module.to_owned();
+        let variable = variable.to_owned();
+        let signature = signature.to_owned();
+
+        self.execute(move |wren| {
+            let reciever: Handle = wren.get_variable(&module, &variable)?;
+
+            let handle = wren.make_call_handle(&signature)?;
+
+            wren.call(&handle, reciever, args)
+        })
+    }
+
+    /// Gets a handle which can interrupt the code currently running on the
+    /// virtual machine.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    fn sender(&self) -> &mpsc::Sender<Command<U, M, W>> {
+        self.sender.as_ref().expect("sender is only taken on drop")
+    }
+}
+
+impl<U, M, W> std::fmt::Debug for AsyncWren<U, M, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncWren").finish_non_exhaustive()
+    }
+}
+
+impl<U, M, W> Drop for AsyncWren<U, M, W> {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread once the queue is empty.
+        self.sender = None;
+
+        if let Some(thread) = self.thread.take() {
+            // A panic on the thread has already been reported to its replies.
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Shared<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+/// A future which resolves to the result of a request made to an
+/// [`AsyncWren`].
+///
+/// If the thread running the virtual machine stops before handling the
+/// request, such as because an earlier request panicked, the reply resolves
+/// to [`Error::Disconnected`].
+#[must_use = "requests are made regardless, but their results are lost if the reply is dropped"]
+pub struct Reply<T>(Arc<Mutex<Shared<T>>>);
+
+impl<T> Reply<T> {
+    fn new() -> (Completer<T>, Reply<T>) {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+
+        (Completer(Some(shared.clone())), Reply(shared))
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Reply<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reply").finish_non_exhaustive()
+    }
+}
+
+/// The sending half of a [`Reply`].
+///
+/// A completer which is dropped without being completed resolves its reply to
+/// [`Error::Disconnected`].
+struct Completer<T>(Option<Arc<Mutex<Shared<T>>>>);
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: Result<T, Error>) {
+        if let Some(shared) = self.0.take() {
+            Completer::resolve(&shared, result);
+        }
+    }
+
+    fn resolve(shared: &Mutex<Shared<T>>, result: Result<T, Error>) {
+        let waker = {
+            let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+
+            shared.result = Some(result);
+            shared.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0.take() {
+            Completer::resolve(&shared, Err(Error::Disconnected));
+        }
+    }
+}
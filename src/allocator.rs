@@ -0,0 +1,326 @@
+//! Allocation of the memory used by a virtual machine.
+//!
+//! Every allocation made by Wren passes through a single reallocation
+//! function, which is only given the new size of an allocation. To be able to
+//! track the number of bytes in use, and to hand the correct [`Layout`] to a
+//! [`WrenAllocator`], each allocation is prefixed with its size.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    os::raw::c_void,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{raw::WrenPtr, wren::WrenHeader};
+
+/// The alignment of every allocation handed to Wren, matching `malloc`.
+const ALIGN: usize = 16;
+
+/// The number of bytes reserved before each allocation to store its size.
+const PREFIX: usize = ALIGN;
+
+/// An allocator which provides the memory used by a virtual machine.
+///
+/// # Safety
+/// Implementors must uphold the same contract as [`GlobalAlloc`]. In
+/// particular, a successful allocation must return a pointer to a block of
+/// memory with the requested layout.
+///
+/// Wren cannot recover from a failed allocation, so an allocator which
+/// returns a null pointer aborts the process through
+/// [`handle_alloc_error`]. An allocator should not refuse allocations to
+/// enforce a quota; [`Builder::with_memory_limit`] stops scripts which use too
+/// much memory without aborting the process.
+///
+/// The virtual machine is freed by the last of its [`Wren`] or handles to be
+/// dropped, which may happen on any thread, and so allocators must be [`Send`].
+///
+/// [`Wren`]: crate::Wren
+/// [`handle_alloc_error`]: std::alloc::handle_alloc_error
+/// [`Builder::with_memory_limit`]: crate::Builder::with_memory_limit
+pub unsafe trait WrenAllocator: Send {
+    /// Allocates a block of memory with the given `layout`.
+    ///
+    /// # Safety
+    /// `layout` must have a non-zero size.
+    unsafe fn allocate(&mut self, layout: Layout) -> *mut u8;
+
+    /// Deallocates the block of memory at `ptr`.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `layout`.
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Shrinks or grows the block of memory at `ptr` to `new_size` bytes.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `layout`, and
+    /// `new_size` must be non-zero.
+    unsafe fn reallocate(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+
+        let new = unsafe { self.allocate(new_layout) };
+
+        if !new.is_null() {
+            let size = layout.size().min(new_size);
+
+            unsafe { std::ptr::copy_nonoverlapping(ptr, new, size) };
+            unsafe { self.deallocate(ptr, layout) };
+        }
+
+        new
+    }
+}
+
+unsafe impl WrenAllocator for System {
+    unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        unsafe { GlobalAlloc::alloc(self, layout) }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { GlobalAlloc::dealloc(self, ptr, layout) }
+    }
+
+    unsafe fn reallocate(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        unsafe { GlobalAlloc::realloc(self, ptr, layout, new_size) }
+    }
+}
+
+/// A snapshot of the memory used by a virtual machine.
+///
+/// Returned by [`Wren::memory_stats`].
+///
+/// [`Wren::memory_stats`]: crate::Wren::memory_stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[non_exhaustive]
+pub struct MemoryStats {
+    /// The number of bytes currently allocated by the virtual machine.
+    pub allocated: usize,
+    /// The largest number of bytes the virtual machine has had allocated at
+    /// once.
+    pub peak_allocated: usize,
+    /// The number of garbage collections run, whether they were started by
+    /// the host or by Wren itself.
+    pub collections: usize,
+    /// The number of live [`Handle`]s and [`CallHandle`]s held by the host.
+    ///
+    /// Handles used internally, such as by fibers waiting on a task or a
+    /// timer, are not counted.
+    ///
+    /// [`Handle`]: crate::value::Handle
+    /// [`CallHandle`]: crate::CallHandle
+    pub handles: usize,
+    /// The number of bytes held by instances of foreign classes.
+    pub foreign_allocated: usize,
+}
+
+/// The memory used by a single virtual machine.
+pub(crate) struct Heap {
+    allocator: Box<dyn WrenAllocator>,
+    allocated: usize,
+    peak: usize,
+    foreign: usize,
+    limit: Option<usize>,
+    exhausted: bool,
+}
+
+impl Heap {
+    pub fn new(allocator: Box<dyn WrenAllocator>, limit: Option<usize>) -> Heap {
+        Heap {
+            allocator,
+            allocated: 0,
+            peak: 0,
+            foreign: 0,
+            limit,
+            exhausted: false,
+        }
+    }
+
+    /// The number of bytes currently allocated by Wren, not including the
+    /// size prefixes.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// The largest value [`Heap::allocated`] has reached.
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// The number of bytes held by foreign objects.
+    pub fn foreign(&self) -> usize {
+        self.foreign
+    }
+
+    /// Records that a foreign object of `size` bytes has been created.
+    pub fn record_foreign(&mut self, size: usize) {
+        self.foreign += size;
+    }
+
+    /// Records that a foreign object of `size` bytes has been finalized.
+    pub fn release_foreign(&mut self, size: usize) {
+        self.foreign -= size;
+    }
+
+    /// Returns `true` if a running fiber was aborted for exceeding the memory
+    /// limit since the last call to [`Heap::clear_exhausted`].
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Clears the exhausted flag, before more code is run.
+    pub fn clear_exhausted(&mut self) {
+        self.exhausted = false;
+    }
+
+    /// Returns `true` if more memory is in use than the limit allows.
+    pub fn is_over_limit(&self) -> bool {
+        self.limit.is_some_and(|limit| self.allocated > limit)
+    }
+
+    fn layout(size: usize) -> Layout {
+        let size = size
+            .checked_add(PREFIX)
+            .expect("allocation size overflowed");
+
+        Layout::from_size_align(size, ALIGN).expect("allocation size overflowed")
+    }
+
+    /// Implements the semantics of the C `reallocateFn`.
+    ///
+    /// Allocations which exceed the memory limit are still made, as Wren
+    /// cannot recover from a failed allocation. Instead, the limit is checked
+    /// by [`is_out_of_memory`] before the running fiber takes its next step.
+    ///
+    /// # Safety
+    /// `ptr` must be null or a pointer previously returned by this function
+    /// on this heap.
+    pub unsafe fn reallocate(&mut self, ptr: *mut u8, new_size: usize) -> *mut u8 {
+        let old = if ptr.is_null() {
+            None
+        } else {
+            let block = unsafe { ptr.sub(PREFIX) };
+            let size = unsafe { block.cast::<usize>().read() };
+
+            Some((block, size))
+        };
+
+        if new_size == 0 {
+            if let Some((block, size)) = old {
+                unsafe { self.allocator.deallocate(block, Heap::layout(size)) };
+
+                self.allocated -= size;
+            }
+
+            return std::ptr::null_mut();
+        }
+
+        let new_layout = Heap::layout(new_size);
+
+        let block = match old {
+            Some((block, size)) => unsafe {
+                self.allocator
+                    .reallocate(block, Heap::layout(size), new_layout.size())
+            },
+            None => unsafe { self.allocator.allocate(new_layout) },
+        };
+
+        if block.is_null() {
+            std::alloc::handle_alloc_error(new_layout);
+        }
+
+        unsafe { block.cast::<usize>().write(new_size) };
+
+        let old_size = old.map_or(0, |(_, size)| size);
+        self.allocated = self.allocated - old_size + new_size;
+        self.peak = self.peak.max(self.allocated);
+
+        unsafe { block.add(PREFIX) }
+    }
+}
+
+/// The reallocation function given to Wren.
+///
+/// An allocation which takes the heap over its limit makes the interpreter
+/// check the limit before the running fiber takes its next step.
+///
+/// # Safety
+/// `user_data` must point to the [`WrenHeader`] of the virtual machine.
+///
+/// [`WrenHeader`]: crate::wren::WrenHeader
+pub(crate) unsafe extern "C" fn reallocate_fn(
+    ptr: *mut c_void,
+    new_size: usize,
+    user_data: *mut c_void,
+) -> *mut c_void {
+    let header = user_data.cast::<WrenHeader>();
+
+    let heap = unsafe { &mut (*header).heap };
+    let before = heap.allocated();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        heap.reallocate(ptr.cast(), new_size)
+    }));
+
+    // Wren cannot recover from a failed allocation, and a panic must not
+    // unwind into it, so a panicking allocator aborts the process, as an
+    // allocation error does.
+    let Ok(ptr) = result else {
+        std::process::abort();
+    };
+
+    // Only a growing heap is checked, as the virtual machine may be in the
+    // middle of being freed.
+    if heap.allocated() > before
+        && heap.is_over_limit()
+        && let Some(vm) = unsafe { (*header).vm }
+    {
+        // Safety: Wren only allocates on the thread running it.
+        unsafe { vm.interrupt_soon() };
+    }
+
+    ptr.cast()
+}
+
+/// Returns `true` if the virtual machine is over its memory limit, even after
+/// collecting garbage, marking the heap as exhausted.
+///
+/// # Safety
+/// Must only be called while no code is running in `vm`, from a foreign method
+/// of `vm`, or from its interrupt callback, as garbage may be collected.
+pub(crate) unsafe fn is_out_of_memory(vm: &WrenPtr) -> bool {
+    let header = vm.get_user_data::<WrenHeader>();
+
+    if !unsafe { (*header).heap.is_over_limit() } {
+        return false;
+    }
+
+    // The values in use by the running fiber are on its stack, and so are not
+    // collected.
+    unsafe { vm.collect_garbage() };
+
+    if !unsafe { (*header).heap.is_over_limit() } {
+        return false;
+    }
+
+    unsafe { (*header).heap.exhausted = true };
+
+    true
+}
+
+/// Aborts the current fiber if the virtual machine is over its memory limit,
+/// even after collecting garbage, returning `true` if it was aborted.
+///
+/// # Safety
+/// Must only be called from a foreign method of `vm`, as the fiber is aborted
+/// using slot zero.
+pub(crate) unsafe fn abort_if_over_limit(vm: &WrenPtr) -> bool {
+    if !unsafe { is_out_of_memory(vm) } {
+        return false;
+    }
+
+    unsafe { vm.set_slot_bytes(0, b"Out of memory.") };
+    unsafe { vm.abort_fiber(0) };
+
+    true
+}
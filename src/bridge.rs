@@ -0,0 +1,220 @@
+//! A module loaded into every virtual machine on demand, used to move values
+//! between Rust and Wren code which cannot receive arguments directly.
+//!
+//! Values are placed in the bridge with a call to `Bridge.stash=(_)` from
+//! Rust, and read back by a snippet of code interpreted in the target module.
+//! That snippet imports the bridge inside a block, so that no variables are
+//! leaked into the user's module. Scripts cannot import the bridge themselves.
+
+use std::ffi::CStr;
+
+/// The name of the module containing the bridge class.
+pub const MODULE: &str = name(MODULE_C);
+
+/// [`MODULE`] as a C string.
+pub const MODULE_C: &CStr = c"wrenlet";
+
+/// The name of the bridge class within [`MODULE`].
+pub const CLASS: &str = name(CLASS_C);
+
+/// [`CLASS`] as a C string.
+pub const CLASS_C: &CStr = c"Bridge";
+
+/// The name under which snippets import [`CLASS`], so that it does not shadow
+/// a variable of the user's module called [`CLASS`].
+///
+/// It is not accepted by [`is_identifier`], so the host cannot define a
+/// variable with this name either.
+pub const ALIAS: &str = "Wrenlet__Bridge";
+
+/// The foreign class wrapping Rust iterators.
+pub const ITERATOR: &CStr = c"Iterator";
+
+/// The foreign class representing running tasks.
+pub const TASK: &CStr = c"Task";
+
+/// The foreign class wrapping Rust closures.
+pub const CLOSURE: &CStr = c"Closure";
+
+/// The foreign class through which the `scheduler` and `timer` modules
+/// schedule fibers.
+pub const CLOCK: &CStr = c"Clock";
+
+/// Converts the name of a class or module defined here to a string.
+pub const fn name(name: &'static CStr) -> &'static str {
+    match name.to_str() {
+        Ok(name) => name,
+        Err(_) => panic!("names must be valid UTF-8"),
+    }
+}
+
+/// The source code of [`MODULE`].
+pub const SOURCE: &str = r#"
+class Bridge {
+  static stash=(value) { __value = value }
+
+  static take() {
+    var value = __value
+    __value = null
+    return value
+  }
+
+  static fiber(function) { Fiber.new(function) }
+
+  static arity(value) { value is Fn ? value.arity : null }
+
+  static resume(fiber, value) { fiber.transfer(value) }
+
+  static fail(fiber, error) { fiber.transferError(error) }
+}
+
+foreign class Iterator is Sequence {
+  foreign iterate(iterator)
+  foreign iteratorValue(iterator)
+}
+
+foreign class Task {
+  foreign park_(fiber)
+
+  await {
+    park_(Fiber.current)
+    return Fiber.suspend()
+  }
+}
+
+foreign class Closure {
+  foreign arity
+  foreign call()
+  foreign call(a)
+  foreign call(a, b)
+  foreign call(a, b, c)
+  foreign call(a, b, c, d)
+  foreign call(a, b, c, d, e)
+  foreign call(a, b, c, d, e, f)
+  foreign call(a, b, c, d, e, f, g)
+  foreign call(a, b, c, d, e, f, g, h)
+}
+
+foreign class Clock {
+  foreign static schedule_(fiber, milliseconds)
+}
+"#;
+
+/// The reserved words of Wren, which cannot be used as names.
+const KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "class",
+    "construct",
+    "continue",
+    "else",
+    "false",
+    "for",
+    "foreign",
+    "if",
+    "import",
+    "in",
+    "is",
+    "null",
+    "return",
+    "static",
+    "super",
+    "this",
+    "true",
+    "var",
+    "while",
+];
+
+/// Returns `true` if `name` can be used as the name of a top level variable.
+///
+/// Names beginning with an underscore are fields in Wren, and so are not
+/// accepted, nor are reserved words or [`ALIAS`].
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+        && name != ALIAS
+}
+
+/// Wraps `body` in a block which imports the bridge class as [`ALIAS`], so
+/// that it can be interpreted in a module without defining any new variables.
+pub fn block(body: &str) -> String {
+    format!("{{\n  import \"{MODULE}\" for {CLASS} as {ALIAS}\n{body}\n}}\n")
+}
+
+/// Creates source code which assigns the stashed value to the top level
+/// variable `name`.
+pub fn assign_from_stash(name: &str) -> String {
+    debug_assert!(is_identifier(name));
+
+    block(&format!("  {name} = {ALIAS}.take()"))
+}
+
+/// The number of lines which [`stash_expression`] places before the
+/// expression.
+pub const EXPRESSION_OFFSET: usize = 2;
+
+/// Creates source code which stashes the value of `expression`.
+pub fn stash_expression(expression: &str) -> String {
+    block(&format!("  {ALIAS}.stash = {expression}"))
+}
+
+/// The number of lines which [`function`] places before the body.
+pub const FUNCTION_OFFSET: usize = 1;
+
+/// Creates an expression which creates a function taking `params`, with the
+/// given `body`.
+///
+/// The body is placed on its own lines, so that a comment at its end does not
+/// hide the closing brace. A body on a single line is an expression, whose
+/// value is returned, as in a Wren block.
+pub fn function(params: &[&str], body: &str) -> String {
+    debug_assert!(params.iter().all(|param| is_identifier(param)));
+
+    let params = match params {
+        [] => String::new(),
+        params => format!("|{}|", params.join(", ")),
+    };
+
+    if body.contains('\n') || body.trim().is_empty() {
+        format!("Fn.new {{{params}\n{body}\n}}")
+    } else {
+        format!("Fn.new {{{params}\nreturn {body}\n}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("SCREEN_W"));
+        assert!(is_identifier("x1"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("_field"));
+        assert!(!is_identifier("1x"));
+        assert!(!is_identifier("x = 1\nSystem.print(x)"));
+        assert!(!is_identifier("class"));
+        assert!(!is_identifier("this"));
+        assert!(is_identifier("classes"));
+        assert!(is_identifier("Bridge"));
+        assert!(!is_identifier(ALIAS));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(function(&[], "1"), "Fn.new {\nreturn 1\n}");
+        assert_eq!(function(&[], ""), "Fn.new {\n\n}");
+        assert_eq!(
+            function(&["a", "b"], "a + b // sum"),
+            "Fn.new {|a, b|\nreturn a + b // sum\n}"
+        );
+        assert_eq!(
+            function(&["a"], "var b = a\nreturn b"),
+            "Fn.new {|a|\nvar b = a\nreturn b\n}"
+        );
+    }
+}
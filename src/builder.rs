@@ -1,16 +1,33 @@
-use std::{io::Stdout, mem::MaybeUninit};
+use std::{alloc::System, io::Stdout, mem::MaybeUninit};
 
 use crate::{
+    allocator::{Heap, WrenAllocator},
     module::{Empty, ModuleLoader},
+    raw::WrenPtr,
     wren::{Wren, WrenData},
 };
 
 /// A builder for an instance of a Wren virtual machine.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
-pub struct Builder<U, M, W> {
+pub struct Builder<U, M, W, A = System> {
     user_data: U,
     loader: M,
     writer: W,
+    allocator: A,
+    config: Config,
+}
+
+/// The options of a [`Builder`] which do not affect its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+struct Config {
+    initial_heap_size: Option<usize>,
+    min_heap_size: Option<usize>,
+    heap_growth_percent: Option<u32>,
+    memory_limit: Option<usize>,
+    step_budget: Option<u64>,
+    meta: bool,
+    random: bool,
+    print_errors: bool,
 }
 
 impl Builder<(), Empty, Stdout> {
@@ -19,55 +36,202 @@ impl Builder<(), Empty, Stdout> {
             user_data: (),
             loader: Empty,
             writer: std::io::stdout(),
+            allocator: System,
+            config: Config {
+                meta: cfg!(feature = "meta"),
+                random: cfg!(feature = "random"),
+                print_errors: true,
+                ..Config::default()
+            },
         }
     }
 }
 
-impl<U, M, W> Builder<U, M, W> {
-    pub fn with_data<T>(self, user_data: T) -> Builder<T, M, W> {
-        let Builder { loader, writer, .. } = self;
+impl<U, M, W, A> Builder<U, M, W, A> {
+    pub fn with_data<T>(self, user_data: T) -> Builder<T, M, W, A> {
+        let Builder {
+            loader,
+            writer,
+            allocator,
+            config,
+            ..
+        } = self;
 
         Builder {
             user_data,
             loader,
             writer,
+            allocator,
+            config,
         }
     }
 
-    pub fn with_loader<T>(self, loader: T) -> Builder<U, T, W>
+    pub fn with_loader<T>(self, loader: T) -> Builder<U, T, W, A>
     where
         T: ModuleLoader,
     {
         let Builder {
-            user_data, writer, ..
+            user_data,
+            writer,
+            allocator,
+            config,
+            ..
         } = self;
 
         Builder {
             user_data,
             loader,
             writer,
+            allocator,
+            config,
         }
     }
 
-    pub fn with_output<T>(self, writer: T) -> Builder<U, M, T>
+    pub fn with_output<T>(self, writer: T) -> Builder<U, M, T, A>
     where
         T: std::io::Write,
     {
         let Builder {
-            user_data, loader, ..
+            user_data,
+            loader,
+            allocator,
+            config,
+            ..
+        } = self;
+
+        Builder {
+            user_data,
+            loader,
+            writer,
+            allocator,
+            config,
+        }
+    }
+
+    /// Sets the number of bytes Wren will allocate before triggering the
+    /// first garbage collection.
+    ///
+    /// Defaults to 10MiB.
+    pub fn initial_heap_size(mut self, bytes: usize) -> Self {
+        self.config.initial_heap_size = Some(bytes);
+        self
+    }
+
+    /// Sets the minimum size of the heap after a garbage collection.
+    ///
+    /// After a collection, the next collection is scheduled based on the
+    /// number of bytes still in use, but never for less than this amount.
+    /// Defaults to 1MiB.
+    pub fn min_heap_size(mut self, bytes: usize) -> Self {
+        self.config.min_heap_size = Some(bytes);
+        self
+    }
+
+    /// Sets how much the heap may grow past the bytes still in use before the
+    /// next garbage collection, as a percentage.
+    ///
+    /// A value of `100` waits until the heap has doubled in size. Defaults to
+    /// `50`.
+    pub fn heap_growth_percent(mut self, percent: u32) -> Self {
+        self.config.heap_growth_percent = Some(percent);
+        self
+    }
+
+    /// Sets the allocator which provides the memory used by the virtual
+    /// machine.
+    ///
+    /// Defaults to the [`System`] allocator.
+    pub fn with_allocator<T>(self, allocator: T) -> Builder<U, M, W, T>
+    where
+        T: WrenAllocator + 'static,
+    {
+        let Builder {
+            user_data,
+            loader,
+            writer,
+            config,
+            ..
         } = self;
 
         Builder {
             user_data,
             loader,
             writer,
+            allocator,
+            config,
         }
     }
 
+    /// Limits the number of bytes the virtual machine may have allocated.
+    ///
+    /// Wren is unable to recover from a failed allocation, so allocations
+    /// past the limit still succeed. Instead, garbage is collected before the
+    /// script takes its next step, and if it is still over the limit it is
+    /// aborted, and its output is discarded. Once control returns to the host,
+    /// the call to [`Wren::interpret`] or [`Wren::call`] returns
+    /// [`Error::OutOfMemory`]. No further code may be run until enough memory
+    /// has been freed by the garbage collector to be back within the limit.
+    ///
+    /// A single step, such as creating a very long string, may still allocate
+    /// well past the limit before it is checked.
+    ///
+    /// [`Error::OutOfMemory`]: crate::error::Error::OutOfMemory
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.config.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Limits the number of steps, which are loop iterations and method calls,
+    /// the code run by each call into the virtual machine may take.
+    ///
+    /// A script which exceeds the budget is aborted, such as a loop like
+    /// `while (true) {}`, and the call to [`Wren::interpret`] or
+    /// [`Wren::call`] returns [`Error::Interrupted`]. The budget is checked
+    /// once every thousand steps, and so it may be exceeded by up to that
+    /// many.
+    ///
+    /// Each call starts with the full budget, including those which resume
+    /// fibers waiting on tasks or timers.
+    ///
+    /// [`Error::Interrupted`]: crate::error::Error::Interrupted
+    pub fn with_step_budget(mut self, steps: u64) -> Self {
+        self.config.step_budget = Some(steps);
+        self
+    }
+
+    /// Sets whether scripts may import Wren's optional `meta` module.
+    ///
+    /// Enabled by default.
+    #[cfg(feature = "meta")]
+    pub fn meta(mut self, enabled: bool) -> Self {
+        self.config.meta = enabled;
+        self
+    }
+
+    /// Sets whether scripts may import Wren's optional `random` module.
+    ///
+    /// Enabled by default.
+    #[cfg(feature = "random")]
+    pub fn random(mut self, enabled: bool) -> Self {
+        self.config.random = enabled;
+        self
+    }
+
+    /// Sets whether compile errors and runtime errors are written to the
+    /// output as they are reported, in the format used by the Wren CLI.
+    ///
+    /// The errors are returned by the call which ran the code either way.
+    /// Enabled by default.
+    pub fn print_errors(mut self, enabled: bool) -> Self {
+        self.config.print_errors = enabled;
+        self
+    }
+
     pub fn build(self) -> Wren<U, M, W>
     where
         M: ModuleLoader,
         W: std::io::Write,
+        A: WrenAllocator + 'static,
     {
         let mut conf = MaybeUninit::uninit();
 
@@ -75,31 +239,128 @@ impl<U, M, W> Builder<U, M, W> {
 
         let mut conf = unsafe { conf.assume_init() };
 
-        let user_data = WrenData::allocate(self.user_data, self.loader, self.writer);
+        if let Some(bytes) = self.config.initial_heap_size {
+            conf.initialHeapSize = bytes;
+        }
+
+        if let Some(bytes) = self.config.min_heap_size {
+            conf.minHeapSize = bytes;
+        }
+
+        if let Some(percent) = self.config.heap_growth_percent {
+            conf.heapGrowthPercent = i32::try_from(percent).unwrap_or(i32::MAX);
+        }
+
+        let heap = Heap::new(Box::new(self.allocator), self.config.memory_limit);
+
+        let user_data = WrenData::allocate(heap, self.user_data, self.loader, self.writer);
+
+        // Safety: The data was just allocated, and is not yet shared.
+        let header = unsafe { WrenData::header_mut(user_data) };
+        header.meta = self.config.meta;
+        header.random = self.config.random;
+        header.step_budget.limit = self.config.step_budget;
+        header.print_errors = self.config.print_errors;
 
         conf.userData = user_data.cast::<core::ffi::c_void>();
 
+        conf.reallocateFn = Some(crate::allocator::reallocate_fn);
         conf.writeFn = Some(c_functions::write_fn::<U, M, W>);
         conf.errorFn = Some(c_functions::error_fn::<U, M, W>);
+        conf.interruptFn = Some(crate::interrupt::interrupt_fn);
+        conf.interruptInterval = crate::interrupt::INTERVAL;
+        conf.resolveModuleFn = Some(c_functions::resolve_module_fn::<U, M, W>);
+        conf.loadModuleFn = Some(c_functions::load_module_fn::<U, M, W>);
         conf.bindForeignClassFn = Some(c_functions::bind_foreign_class_fn);
         conf.bindForeignMethodFn = Some(c_functions::bind_foreign_method_fn);
 
         let ptr = unsafe { sys::wrenNewVM(&mut conf) };
-        unsafe { Wren::from_ptr(ptr) }
+        let mut wren = unsafe { Wren::from_ptr(ptr) };
+
+        // Safety: No code has been run yet, so nothing else is using the data.
+        unsafe { WrenData::header_mut(user_data) }.vm = Some(unsafe { WrenPtr::from_raw(ptr) });
+
+        // The bridge module defines the classes of values passed in from Rust,
+        // which cannot be loaded while a foreign method is running. If it
+        // fails to load, the error is returned again by the first call which
+        // needs it.
+        let _ = wren.load_bridge();
+
+        wren
     }
 }
 
 mod c_functions {
-    use std::{ffi::CStr, mem::ManuallyDrop};
+    use std::{
+        borrow::Cow,
+        ffi::{CStr, CString},
+        io::Write,
+        mem::ManuallyDrop,
+        panic::{self, AssertUnwindSafe},
+    };
+
+    use crate::{
+        bridge,
+        error::{CompileError, Error, Panic, RuntimeError, StackFrame},
+        foreigns,
+        module::ModuleLoader,
+        pool,
+        raw::WrenPtr,
+        scheduler,
+        wren::{Wren, WrenHeader},
+    };
+
+    /// Runs `callback`, returning `fallback` if it panics.
+    ///
+    /// A panic cannot unwind into Wren, and so it is caught, and recorded as
+    /// the error of the virtual machine instead.
+    ///
+    /// # Safety
+    /// The user data of `vm` must point to a valid `WrenHeader`.
+    unsafe fn guard<T>(vm: *mut sys::WrenVM, fallback: T, callback: impl FnOnce() -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(callback)) {
+            Ok(value) => value,
+            Err(payload) => {
+                unsafe { record_error(vm, Error::Panic(Panic::new(payload))) };
+
+                fallback
+            }
+        }
+    }
 
-    use crate::{raw::WrenPtr, wren::Wren};
+    /// Records `error` to be returned once control returns to the host,
+    /// unless an earlier error has already been recorded.
+    ///
+    /// # Safety
+    /// The user data of `vm` must point to a valid `WrenHeader`.
+    unsafe fn record_error(vm: *mut sys::WrenVM, error: Error) {
+        let header = unsafe { WrenPtr::from_raw(vm) }.get_user_data::<WrenHeader>();
+
+        // Safety: The error is only accessed by the thread running the
+        // virtual machine, and no reference to it is held while it runs.
+        unsafe { (*header).error.get_or_insert(error) };
+    }
 
-    pub unsafe extern "C" fn write_fn<U, M, W>(vm: *mut sys::WrenVM, text: *const i8) {
-        let _wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
+    pub unsafe extern "C" fn write_fn<U, M, W>(vm: *mut sys::WrenVM, text: *const i8)
+    where
+        W: Write,
+    {
+        let mut wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
+        let header = unsafe { WrenPtr::from_raw(vm) }.get_user_data::<WrenHeader>();
+
+        // The fiber cannot be aborted from here, so the output of a script
+        // over its memory limit is discarded until the error is returned.
+        if unsafe { (*header).heap.is_over_limit() } {
+            return;
+        }
 
         let text = unsafe { CStr::from_ptr(text) };
 
-        print!("{}", text.to_string_lossy());
+        let result = unsafe { guard(vm, Ok(()), || wren.writer_mut().write_all(text.to_bytes())) };
+
+        if let Err(error) = result {
+            unsafe { record_error(vm, error.into()) };
+        }
     }
 
     pub unsafe extern "C" fn error_fn<U, M, W>(
@@ -108,58 +369,264 @@ mod c_functions {
         module: *const i8,
         line: i32,
         message: *const i8,
-    ) {
-        let _wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
-
-        match error_type {
-            sys::WrenErrorType::WREN_ERROR_COMPILE => {
-                let module = unsafe { CStr::from_ptr(module) };
-                let message = unsafe { CStr::from_ptr(message) };
-
-                println!(
-                    "[{} line {line}] [Error] {}",
-                    module.to_string_lossy(),
-                    message.to_string_lossy()
-                );
-            }
-            sys::WrenErrorType::WREN_ERROR_RUNTIME => {
-                let message = unsafe { CStr::from_ptr(message) };
-
-                println!("[Runtime Error] {}", message.to_string_lossy());
+    ) where
+        W: Write,
+    {
+        let mut wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
+        let header = unsafe { WrenPtr::from_raw(vm) }.get_user_data::<WrenHeader>();
+
+        let line = usize::try_from(line).unwrap_or(0);
+
+        let record = || {
+            // Safety: The errors are only accessed by the thread running the
+            // virtual machine, and no reference to them is held while it runs.
+            match error_type {
+                sys::WrenErrorType::WREN_ERROR_COMPILE => {
+                    let module = unsafe { CStr::from_ptr(module) };
+                    let message = unsafe { CStr::from_ptr(message) };
+
+                    let error = CompileError::new(
+                        module.to_string_lossy().into_owned(),
+                        line,
+                        message.to_string_lossy().into_owned(),
+                    );
+
+                    unsafe { (*header).compile_errors.push(error) };
+                }
+                sys::WrenErrorType::WREN_ERROR_RUNTIME => {
+                    let message = unsafe { CStr::from_ptr(message) };
+
+                    let mut error = RuntimeError::new(message.to_string_lossy().into_owned());
+
+                    // A panic in a foreign method aborts its fiber with the
+                    // panic message, which is reported here if the fiber was
+                    // not caught.
+                    if let Some(panic) = unsafe { (*header).panic.take() }
+                        && panic.message() == error.message()
+                    {
+                        error.set_panic(panic);
+                    }
+
+                    unsafe { (*header).runtime_error = error };
+                }
+                sys::WrenErrorType::WREN_ERROR_STACK_TRACE => {
+                    let module = unsafe { CStr::from_ptr(module) };
+                    let function = unsafe { CStr::from_ptr(message) };
+
+                    let frame = StackFrame::new(
+                        module.to_string_lossy().into_owned(),
+                        line,
+                        function.to_string_lossy().into_owned(),
+                    );
+
+                    unsafe { (*header).runtime_error.push_frame(frame) };
+                }
+                _ => unreachable!(),
             }
-            sys::WrenErrorType::WREN_ERROR_STACK_TRACE => {
-                let module = unsafe { CStr::from_ptr(module) };
-                let method = unsafe { CStr::from_ptr(message) };
-
-                println!(
-                    "[{} line {line}] in {}",
-                    module.to_string_lossy(),
-                    method.to_string_lossy()
-                );
+        };
+
+        let print = |writer: &mut W| -> std::io::Result<()> {
+            match error_type {
+                sys::WrenErrorType::WREN_ERROR_COMPILE => {
+                    let module = unsafe { CStr::from_ptr(module) };
+                    let message = unsafe { CStr::from_ptr(message) };
+
+                    writeln!(
+                        writer,
+                        "[{} line {line}] [Error] {}",
+                        module.to_string_lossy(),
+                        message.to_string_lossy()
+                    )
+                }
+                sys::WrenErrorType::WREN_ERROR_RUNTIME => {
+                    let message = unsafe { CStr::from_ptr(message) };
+
+                    writeln!(writer, "[Runtime Error] {}", message.to_string_lossy())
+                }
+                sys::WrenErrorType::WREN_ERROR_STACK_TRACE => {
+                    let module = unsafe { CStr::from_ptr(module) };
+                    let method = unsafe { CStr::from_ptr(message) };
+
+                    writeln!(
+                        writer,
+                        "[{} line {line}] in {}",
+                        module.to_string_lossy(),
+                        method.to_string_lossy()
+                    )
+                }
+                _ => unreachable!(),
             }
-            _ => unreachable!(),
+        };
+
+        let result = unsafe {
+            guard(vm, Ok(()), || {
+                record();
+
+                if (*header).print_errors {
+                    print(wren.writer_mut())
+                } else {
+                    Ok(())
+                }
+            })
+        };
+
+        if let Err(error) = result {
+            unsafe { record_error(vm, error.into()) };
         }
     }
 
+    pub unsafe extern "C" fn resolve_module_fn<U, M, W>(
+        vm: *mut sys::WrenVM,
+        importer: *const i8,
+        name: *const i8,
+    ) -> *const i8
+    where
+        M: ModuleLoader,
+    {
+        let wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
+        let header = unsafe { WrenPtr::from_raw(vm) }.get_user_data::<WrenHeader>();
+
+        let module = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+
+        // The bridge is always loaded before it is imported, and must not be
+        // renamed by the loader. Scripts may not import it themselves.
+        if module == bridge::MODULE {
+            let allowed = std::mem::replace(unsafe { &mut (*header).bridge_import }, false);
+
+            return if allowed { name } else { std::ptr::null() };
+        }
+
+        let (meta, random) = unsafe { ((*header).meta, (*header).random) };
+
+        if (module == "meta" && !meta) || (module == "random" && !random) {
+            return std::ptr::null();
+        }
+
+        // The module of one pooled request must not be visible to another.
+        if module.starts_with(pool::REQUEST_MODULE_PREFIX) {
+            return std::ptr::null();
+        }
+
+        let importer = unsafe { CStr::from_ptr(importer) }.to_string_lossy();
+
+        // A module which fails to resolve is reported by Wren as an error.
+        let resolved = unsafe {
+            guard(vm, Err(()), || {
+                let resolved = wren.loader().resolve(&importer, &module);
+
+                Ok(resolved.map(|resolved| resolved.into_owned()))
+            })
+        };
+
+        let Ok(resolved) = resolved else {
+            return std::ptr::null();
+        };
+
+        let Some(resolved) = resolved else {
+            return name;
+        };
+
+        if resolved == module {
+            return name;
+        }
+
+        // Wren takes ownership of the resolved name, and frees it through its
+        // allocator.
+        let Ok(resolved) = CString::new(resolved) else {
+            return std::ptr::null();
+        };
+        let bytes = resolved.as_bytes_with_nul();
+
+        let ptr = unsafe { (*header).heap.reallocate(std::ptr::null_mut(), bytes.len()) };
+
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len()) };
+
+        ptr.cast()
+    }
+
+    pub unsafe extern "C" fn load_module_fn<U, M, W>(
+        vm: *mut sys::WrenVM,
+        name: *const i8,
+    ) -> sys::WrenLoadModuleResult
+    where
+        M: ModuleLoader,
+    {
+        let wren = ManuallyDrop::new(unsafe { Wren::<U, M, W>::from_ptr(vm) });
+        let header = unsafe { WrenPtr::from_raw(vm) }.get_user_data::<WrenHeader>();
+
+        let module = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+
+        // The built in modules are only used if the loader does not provide
+        // modules with the same names. Each imports the bridge before running
+        // any other code.
+        let load = || {
+            let source = wren.loader().load(&module).or_else(|| {
+                let source = scheduler::builtin_module(&module)?;
+
+                unsafe { (*header).bridge_import = true };
+
+                Some(Cow::Borrowed(source))
+            });
+
+            source.and_then(|source| CString::new(source.into_owned()).ok())
+        };
+
+        match unsafe { guard(vm, None, load) } {
+            Some(source) => sys::WrenLoadModuleResult {
+                source: source.into_raw(),
+                onComplete: Some(load_module_complete_fn),
+                userData: std::ptr::null_mut(),
+            },
+            // Leaving the source null lets Wren fall back to its optional
+            // modules.
+            None => sys::WrenLoadModuleResult {
+                source: std::ptr::null(),
+                onComplete: None,
+                userData: std::ptr::null_mut(),
+            },
+        }
+    }
+
+    unsafe extern "C" fn load_module_complete_fn(
+        _vm: *mut sys::WrenVM,
+        _name: *const i8,
+        result: sys::WrenLoadModuleResult,
+    ) {
+        // Safety: The source was created by `CString::into_raw` in `load_module_fn`.
+        drop(unsafe { CString::from_raw(result.source.cast_mut()) });
+    }
+
     pub unsafe extern "C" fn bind_foreign_class_fn(
         vm: *mut sys::WrenVM,
         module: *const i8,
         class_name: *const i8,
     ) -> sys::WrenForeignClassMethods {
-        let _wren = unsafe { WrenPtr::from_raw(vm) };
+        let wren = unsafe { WrenPtr::from_raw(vm) };
 
-        assert!(!module.is_null());
-        assert!(!class_name.is_null());
+        let unbound = sys::WrenForeignClassMethods {
+            allocate: None,
+            finalize: None,
+        };
 
-        let module = unsafe { CStr::from_ptr(module) };
-        let class_name = unsafe { CStr::from_ptr(class_name) };
+        let bind = || {
+            assert!(!module.is_null());
+            assert!(!class_name.is_null());
 
-        dbg!(module, class_name);
+            let module = unsafe { CStr::from_ptr(module) };
+            let class_name = unsafe { CStr::from_ptr(class_name) };
 
-        sys::WrenForeignClassMethods {
-            allocate: None,
-            finalize: None,
-        }
+            // Unknown classes are left unbound, so that Wren can fall back to
+            // the classes of its optional modules.
+            let finalize = unsafe { foreigns::find_class(&wren, module, class_name) }
+                .map(|class| class.drop_fn);
+
+            sys::WrenForeignClassMethods {
+                allocate: None,
+                finalize,
+            }
+        };
+
+        unsafe { guard(vm, unbound, bind) }
     }
 
     pub unsafe extern "C" fn bind_foreign_method_fn(
@@ -169,18 +636,30 @@ mod c_functions {
         is_static: bool,
         signature: *const i8,
     ) -> Option<unsafe extern "C" fn(*mut sys::WrenVM)> {
-        let _wren = unsafe { WrenPtr::from_raw(vm) };
-
-        assert!(!module.is_null());
-        assert!(!class_name.is_null());
-        assert!(!signature.is_null());
-
-        let module = unsafe { CStr::from_ptr(module) };
-        let class_name = unsafe { CStr::from_ptr(class_name) };
-        let signature = unsafe { CStr::from_ptr(signature) };
-
-        dbg!(module, class_name, is_static, signature);
-
-        None
+        let wren = unsafe { WrenPtr::from_raw(vm) };
+
+        let bind = || {
+            assert!(!module.is_null());
+            assert!(!class_name.is_null());
+            assert!(!signature.is_null());
+
+            let module = unsafe { CStr::from_ptr(module) };
+            let class_name = unsafe { CStr::from_ptr(class_name) };
+            let signature = unsafe { CStr::from_ptr(signature) };
+
+            let method = unsafe { foreigns::find_class(&wren, module, class_name) }
+                .and_then(|class| class.method(is_static, signature));
+
+            // Async methods are always static.
+            method.or_else(|| {
+                is_static
+                    .then(|| unsafe {
+                        foreigns::find_async_method(&wren, module, class_name, signature)
+                    })
+                    .flatten()
+            })
+        };
+
+        unsafe { guard(vm, None, bind) }
     }
 }
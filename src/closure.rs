@@ -0,0 +1,226 @@
+//! Rust closures exposed to Wren as callable objects.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use ::sealed::sealed;
+
+use crate::{
+    bridge,
+    error::Error,
+    foreigns::{self, ForeignClass, ForeignMethod},
+    raw::WrenPtr,
+    value::{FromWren, IntoWren},
+};
+
+/// The most parameters a closure can take.
+const MAX_ARITY: usize = 8;
+
+/// Reads the arguments from the slots of a call, runs the closure, and places
+/// its return value in slot zero, or returns the message with which to abort.
+type BoxedFn = Box<dyn FnMut(&WrenPtr) -> Result<(), String> + Send>;
+
+/// A Rust closure, which Wren code can call like a function.
+///
+/// When passed to Wren, a closure becomes an instance of the `Closure` class
+/// of the `wrenlet` module, which has the same `call` methods and `arity`
+/// getter as `Fn`. It can therefore be used wherever Wren expects a function
+/// to be called, such as by `map` and `where`, although it is not an instance
+/// of `Fn` itself.
+///
+/// Arguments are converted with [`FromWren`], and the return value with
+/// [`IntoWren`]. Calling a closure with too few arguments, or with arguments
+/// of the wrong types, aborts the calling fiber. Extra arguments are ignored,
+/// as they are for `Fn`.
+///
+/// Each time a closure is passed to Wren, the new Wren object shares the same
+/// underlying Rust closure.
+///
+/// A closure may capture a [`Handle`], [`WrenFn`] or [`Fiber`] from the
+/// virtual machine it is passed to. The copies of the closure held by Wren are
+/// dropped along with the [`Wren`], releasing what they captured, but a copy
+/// kept by Rust holds on to the virtual machine until it is dropped.
+///
+/// [`Handle`]: crate::value::Handle
+/// [`WrenFn`]: crate::WrenFn
+/// [`Fiber`]: crate::Fiber
+/// [`Wren`]: crate::Wren
+///
+/// ```
+/// # use wrenlet::{Wren, value::Closure};
+/// let mut wren = Wren::new();
+///
+/// let mut calls = 0;
+/// let scale = Closure::new(move |x: f64| {
+///     calls += 1;
+///     x * 10.0
+/// });
+///
+/// wren.interpret("main", "var scale = null").unwrap();
+/// wren.set_variable("main", "scale", scale).unwrap();
+///
+/// wren.interpret("main", "var total = [1, 2, 3].map(scale).reduce {|a, b| a + b }")
+///     .unwrap();
+///
+/// assert_eq!(wren.get_variable::<f64>("main", "total").unwrap(), 60.0);
+/// ```
+#[derive(Clone)]
+pub struct Closure {
+    arity: usize,
+    function: Arc<Mutex<BoxedFn>>,
+}
+
+impl Closure {
+    /// Creates a closure from the given Rust function, which may take up to
+    /// eight parameters.
+    pub fn new<Args>(function: impl IntoClosure<Args>) -> Closure {
+        function.into_closure()
+    }
+
+    /// The number of parameters the closure takes.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Places a new Wren object sharing this closure in `slot`.
+    pub(crate) fn put(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        // Safety: The class is registered for `Closure`.
+        unsafe { foreigns::new_builtin(wren, slot, bridge::CLOSURE, self.clone()) }
+    }
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Closure")
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A Rust function which can be made into a [`Closure`].
+///
+/// This trait is implemented for functions taking zero to eight parameters,
+/// each of which implements [`FromWren`] for any lifetime, and returning a
+/// value which implements [`IntoWren`].
+#[sealed]
+pub trait IntoClosure<Args> {
+    fn into_closure(self) -> Closure;
+}
+
+/// Reads the argument in `slot`, or returns the message with which to abort.
+fn argument<T: for<'a> FromWren<'a>>(wren: &WrenPtr, slot: usize) -> Result<T, String> {
+    T::get_value(wren, slot).map_err(|_| format!("Argument {slot} has the wrong type."))
+}
+
+macro_rules! impl_into_closure {
+    ($arity:literal; $($arg:ident $slot:literal),*; $($rev:ident $rev_slot:literal),*) => {
+        #[sealed]
+        impl<F, R, $($arg),*> IntoClosure<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> R + Send + 'static,
+            R: IntoWren,
+            $($arg: for<'a> FromWren<'a>,)*
+        {
+            #[allow(non_snake_case)]
+            fn into_closure(mut self) -> Closure {
+                let function = move |wren: &WrenPtr| {
+                    // Arguments are read from last to first, as reading a
+                    // list uses the slot above it.
+                    $(let $rev: $rev = argument(wren, $rev_slot)?;)*
+
+                    self($($arg),*)
+                        .put_value(wren, 0)
+                        .map_err(|_| "The return value cannot be passed to Wren.".to_owned())
+                };
+
+                Closure {
+                    arity: $arity,
+                    function: Arc::new(Mutex::new(Box::new(function))),
+                }
+            }
+        }
+    };
+}
+
+impl_into_closure!(0;;);
+impl_into_closure!(1; A 1; A 1);
+impl_into_closure!(2; A 1, B 2; B 2, A 1);
+impl_into_closure!(3; A 1, B 2, C 3; C 3, B 2, A 1);
+impl_into_closure!(4; A 1, B 2, C 3, D 4; D 4, C 3, B 2, A 1);
+impl_into_closure!(5; A 1, B 2, C 3, D 4, E 5; E 5, D 4, C 3, B 2, A 1);
+impl_into_closure!(6; A 1, B 2, C 3, D 4, E 5, G 6; G 6, E 5, D 4, C 3, B 2, A 1);
+impl_into_closure!(7; A 1, B 2, C 3, D 4, E 5, G 6, H 7; H 7, G 6, E 5, D 4, C 3, B 2, A 1);
+impl_into_closure!(8; A 1, B 2, C 3, D 4, E 5, G 6, H 7, I 8; I 8, H 7, G 6, E 5, D 4, C 3, B 2, A 1);
+
+/// The foreign class registered for [`Closure`].
+pub(crate) fn class() -> ForeignClass {
+    let calls = [
+        "call()",
+        "call(_)",
+        "call(_,_)",
+        "call(_,_,_)",
+        "call(_,_,_,_)",
+        "call(_,_,_,_,_)",
+        "call(_,_,_,_,_,_)",
+        "call(_,_,_,_,_,_,_)",
+        "call(_,_,_,_,_,_,_,_)",
+    ];
+
+    debug_assert_eq!(calls.len(), MAX_ARITY + 1);
+
+    ForeignClass::new_for::<Closure>(
+        bridge::MODULE,
+        bridge::name(bridge::CLOSURE),
+        calls
+            .into_iter()
+            .map(|signature| ForeignMethod::new(signature, call))
+            .chain([ForeignMethod::new("arity", arity)]),
+    )
+}
+
+/// Implements the `call` methods of `Closure`.
+unsafe extern "C" fn call(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        let Some(closure) = (unsafe { foreigns::receiver::<Closure>(wren) }) else {
+            return;
+        };
+
+        // The closure is shared, so that it outlives the reciever once its slot
+        // is overwritten.
+        let closure = closure.clone();
+
+        if wren.get_slot_count() - 1 < closure.arity {
+            unsafe { foreigns::abort(wren, "Function expects more arguments.") };
+            return;
+        }
+
+        let result = {
+            let mut function = closure
+                .function
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            function(wren)
+        };
+
+        if let Err(message) = result {
+            unsafe { foreigns::abort(wren, &message) };
+        }
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
+
+/// Implements `Closure.arity`.
+unsafe extern "C" fn arity(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        let Some(closure) = (unsafe { foreigns::receiver::<Closure>(wren) }) else {
+            return;
+        };
+
+        let arity = closure.arity as f64;
+
+        unsafe { wren.set_slot_double(0, arity) };
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
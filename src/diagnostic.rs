@@ -0,0 +1,392 @@
+//! Human readable reports of the errors raised by Wren code.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
+
+use crate::{
+    error::{CompileError, Error, RuntimeError},
+    module::ModuleLoader,
+};
+
+/// A report of an [`Error`], which renders the source line at which it
+/// occurred, marked with carets, and the stack trace of a runtime error.
+///
+/// The source code of each module is taken from those added by
+/// [`with_source`] and [`with_loader`]. Modules whose source is unknown, such
+/// as those passed directly to [`Wren::interpret`], are reported by name and
+/// line alone.
+///
+/// A diagnostic is rendered as plain text by its [`Display`] implementation,
+/// or with ANSI colours by [`ansi`].
+///
+/// ```
+/// # use wrenlet::{Diagnostic, Wren};
+/// let mut wren = Wren::new();
+///
+/// let source = "var a = 1\nvar b = a.foo";
+/// let error = wren.interpret("main", source).unwrap_err();
+///
+/// let diagnostic = Diagnostic::new(&error).with_source("main", source);
+///
+/// assert_eq!(
+///     diagnostic.to_string(),
+///     "\
+/// error: Num does not implement 'foo'.
+///  --> main:2
+///   |
+/// 2 | var b = a.foo
+///   | ^^^^^^^^^^^^^
+///   |
+///   = stack trace:
+///     (script) at main:2
+/// "
+/// );
+/// ```
+///
+/// [`with_source`]: Diagnostic::with_source
+/// [`with_loader`]: Diagnostic::with_loader
+/// [`Wren::interpret`]: crate::Wren::interpret
+/// [`Display`]: fmt::Display
+/// [`ansi`]: Diagnostic::ansi
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    reports: Vec<Report>,
+    sources: HashMap<String, String>,
+}
+
+/// A single error within a [`Diagnostic`].
+#[derive(Debug, Clone)]
+struct Report {
+    message: String,
+    location: Option<Location>,
+    stack_trace: Vec<Frame>,
+}
+
+#[derive(Debug, Clone)]
+struct Location {
+    module: String,
+    line: usize,
+    /// The token at which the error was found, if Wren reported one.
+    token: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    function: String,
+    module: String,
+    line: usize,
+}
+
+/// The escape codes used to colour a rendered diagnostic.
+struct Style {
+    error: &'static str,
+    message: &'static str,
+    gutter: &'static str,
+    reset: &'static str,
+}
+
+impl Style {
+    const PLAIN: Style = Style {
+        error: "",
+        message: "",
+        gutter: "",
+        reset: "",
+    };
+
+    const ANSI: Style = Style {
+        error: "\x1b[1;31m",
+        message: "\x1b[1m",
+        gutter: "\x1b[1;34m",
+        reset: "\x1b[0m",
+    };
+}
+
+impl Diagnostic {
+    /// Creates a report of `error`.
+    ///
+    /// Each [`CompileError`] is reported separately, while errors without a
+    /// location in the source code are reported by their description alone.
+    pub fn new(error: &Error) -> Diagnostic {
+        let reports = match error {
+            Error::Compile(errors) => errors.iter().map(Report::compile).collect(),
+            Error::Runtime(error) => vec![Report::runtime(error)],
+            error => vec![Report {
+                message: describe(error),
+                location: None,
+                stack_trace: Vec::new(),
+            }],
+        };
+
+        Diagnostic {
+            reports,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Uses `source` as the source code of `module`.
+    pub fn with_source(mut self, module: impl Into<String>, source: impl Into<String>) -> Self {
+        self.sources.insert(module.into(), source.into());
+        self
+    }
+
+    /// Loads the source code of each module in the report which has not
+    /// already been added with `loader`.
+    pub fn with_loader(mut self, loader: &impl ModuleLoader) -> Self {
+        let modules = self.reports.iter().flat_map(|report| {
+            let location = report.location.iter().map(|location| &location.module);
+            let frames = report.stack_trace.iter().map(|frame| &frame.module);
+
+            location.chain(frames)
+        });
+
+        for module in modules {
+            if self.sources.contains_key(module) {
+                continue;
+            }
+
+            if let Some(source) = loader.load(module) {
+                self.sources.insert(module.clone(), source.into_owned());
+            }
+        }
+
+        self
+    }
+
+    /// Renders the diagnostic with ANSI escape codes for colour, for display
+    /// in a terminal.
+    pub fn ansi(&self) -> impl fmt::Display + '_ {
+        Ansi(self)
+    }
+
+    /// Returns line `line` of `module`, counting from one, if its source code
+    /// is known.
+    fn source_line(&self, module: &str, line: usize) -> Option<String> {
+        let source = self.sources.get(module)?;
+        let text = source.lines().nth(line.checked_sub(1)?)?;
+
+        Some(text.replace('\t', "    "))
+    }
+
+    fn render(&self, f: &mut impl Write, style: &Style) -> fmt::Result {
+        for (index, report) in self.reports.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            self.render_report(f, report, style)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_report(&self, f: &mut impl Write, report: &Report, style: &Style) -> fmt::Result {
+        let Style {
+            error,
+            message,
+            gutter,
+            reset,
+        } = style;
+
+        writeln!(f, "{error}error{reset}{message}: {}{reset}", report.message)?;
+
+        let Some(location) = &report.location else {
+            return Ok(());
+        };
+
+        let width = location.line.to_string().len();
+        let pad = "";
+
+        writeln!(
+            f,
+            "{pad:width$}{gutter}-->{reset} {}:{}",
+            location.module, location.line
+        )?;
+
+        if let Some(text) = self.source_line(&location.module, location.line) {
+            let (start, len) = underline(&text, location.token.as_deref());
+
+            writeln!(f, "{pad:width$} {gutter}|{reset}")?;
+            writeln!(f, "{gutter}{:width$} |{reset} {text}", location.line)?;
+            writeln!(
+                f,
+                "{pad:width$} {gutter}|{reset} {pad:start$}{error}{}{reset}",
+                "^".repeat(len)
+            )?;
+        }
+
+        if report.stack_trace.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f, "{pad:width$} {gutter}|{reset}")?;
+        writeln!(
+            f,
+            "{pad:width$} {gutter}={reset} {message}stack trace:{reset}"
+        )?;
+
+        for frame in &report.stack_trace {
+            writeln!(
+                f,
+                "{pad:width$}   {} at {}:{}",
+                frame.function, frame.module, frame.line
+            )?;
+
+            if frame.module == location.module && frame.line == location.line {
+                continue;
+            }
+
+            if let Some(text) = self.source_line(&frame.module, frame.line) {
+                writeln!(
+                    f,
+                    "{pad:width$}     {gutter}{} |{reset} {}",
+                    frame.line,
+                    text.trim()
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, &Style::PLAIN)
+    }
+}
+
+/// A [`Diagnostic`] rendered with ANSI colours.
+struct Ansi<'a>(&'a Diagnostic);
+
+impl fmt::Display for Ansi<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.render(f, &Style::ANSI)
+    }
+}
+
+impl Report {
+    fn compile(error: &CompileError) -> Report {
+        let (token, message) = split_token(error.message());
+
+        Report {
+            message: message.to_owned(),
+            location: Some(Location {
+                module: error.module().to_owned(),
+                line: error.line(),
+                token: token.map(str::to_owned),
+            }),
+            stack_trace: Vec::new(),
+        }
+    }
+
+    fn runtime(error: &RuntimeError) -> Report {
+        let stack_trace: Vec<_> = error
+            .stack_trace()
+            .iter()
+            .map(|frame| Frame {
+                function: frame.function().to_owned(),
+                module: frame.module().to_owned(),
+                line: frame.line(),
+            })
+            .collect();
+
+        // The error is reported where the innermost call was running.
+        let location = stack_trace.first().map(|frame| Location {
+            module: frame.module.clone(),
+            line: frame.line,
+            token: None,
+        });
+
+        Report {
+            message: error.message().to_owned(),
+            location,
+            stack_trace,
+        }
+    }
+}
+
+/// Splits a compile error message such as `Error at 'x': Expect ')'.` into
+/// the token at which the error was found, if any, and its description.
+fn split_token(message: &str) -> (Option<&str>, &str) {
+    if let Some(rest) = message.strip_prefix("Error at '")
+        && let Some((token, description)) = rest.split_once("': ")
+    {
+        return (Some(token), description);
+    }
+
+    let description = ["Error at newline: ", "Error at end of file: ", "Error: "]
+        .iter()
+        .find_map(|prefix| message.strip_prefix(prefix))
+        .unwrap_or(message);
+
+    (None, description)
+}
+
+/// Returns the column and length, in characters, of the part of `text` to
+/// mark: the first occurrence of `token`, or otherwise the whole line without
+/// its indentation.
+fn underline(text: &str, token: Option<&str>) -> (usize, usize) {
+    if let Some(token) = token.filter(|token| !token.is_empty())
+        && let Some(index) = text.find(token)
+    {
+        return (text[..index].chars().count(), token.chars().count());
+    }
+
+    let trimmed = text.trim();
+    let start = text.len() - text.trim_start().len();
+
+    (
+        text[..start].chars().count(),
+        trimmed.chars().count().max(1),
+    )
+}
+
+/// Describes an error which has no location in the source code.
+fn describe(error: &Error) -> String {
+    match error {
+        Error::Runtime(error) => error.message().to_owned(),
+        Error::Compile(_) => "The source code could not be compiled.".to_owned(),
+        Error::NoSuchModule => "The module has not been loaded.".to_owned(),
+        Error::NoSuchVariable => "The variable does not exist.".to_owned(),
+        Error::InvalidIdentifier => "The name is not a valid identifier.".to_owned(),
+        Error::OutOfMemory => "The virtual machine ran out of memory.".to_owned(),
+        Error::Interrupted => "The code was interrupted.".to_owned(),
+        Error::Disconnected => "The virtual machine has stopped.".to_owned(),
+        Error::NotAFunction => "The value is not a function.".to_owned(),
+        Error::InteriorNul => "The string contains a NUL byte.".to_owned(),
+        Error::Io(error) => format!("The source code could not be read: {error}"),
+        Error::ArityMismatch { expected, found } => {
+            format!("The function expects {expected} arguments, but was given {found}.")
+        }
+        Error::MismatchedValue(error) => format!("The value has the wrong type: {error:?}"),
+        Error::NestingTooDeep => "The lists are nested too deeply.".to_owned(),
+        Error::Panic(panic) => format!("Rust code panicked: {}", panic.message()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            split_token("Error at ')': Expected expression."),
+            (Some(")"), "Expected expression.")
+        );
+        assert_eq!(
+            split_token("Error at 'x': Expect ')' after arguments."),
+            (Some("x"), "Expect ')' after arguments.")
+        );
+        assert_eq!(
+            split_token("Error at end of file: Expect '}'."),
+            (None, "Expect '}'.")
+        );
+
+        assert_eq!(underline("  var b = )", Some(")")), (10, 1));
+        assert_eq!(underline("  var b = )", Some("missing")), (2, 9));
+        assert_eq!(underline("", None), (0, 1));
+    }
+}
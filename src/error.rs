@@ -1,10 +1,91 @@
 //! Error values which may be returned by this library.
 
+use std::{
+    any::Any,
+    sync::{Arc, Mutex, PoisonError},
+};
+
 #[derive(Debug, Clone)]
 pub enum Error {
-    Runtime,
-    Compile,
+    /// The running code aborted with a runtime error.
+    Runtime(RuntimeError),
+    /// The source code could not be compiled, for the given reasons.
+    Compile(Vec<CompileError>),
+    /// The requested module has not been imported into the virtual machine.
+    NoSuchModule,
+    /// The requested top level variable does not exist in the module.
+    NoSuchVariable,
+    /// The given name is not a valid Wren identifier.
+    InvalidIdentifier,
+    /// The virtual machine exceeded its memory limit.
+    OutOfMemory,
+    /// The running code was stopped by an [`InterruptHandle`].
+    ///
+    /// [`InterruptHandle`]: crate::InterruptHandle
+    Interrupted,
+    /// The thread running an [`AsyncWren`] stopped before the request was
+    /// handled.
+    ///
+    /// [`AsyncWren`]: crate::AsyncWren
+    Disconnected,
+    /// The value is not a Wren function.
+    NotAFunction,
+    /// A string passed to Wren contained a NUL byte, which Wren cannot
+    /// represent.
+    InteriorNul,
+    /// Source code could not be read.
+    Io(Arc<std::io::Error>),
+    /// A Wren function was called with the wrong number of arguments.
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
     MismatchedValue(MismatchedValueError),
+    /// A list copied out of the virtual machine was nested more than
+    /// [`MAX_LIST_DEPTH`] deep, such as a list which contains itself.
+    ///
+    /// [`MAX_LIST_DEPTH`]: crate::value::MAX_LIST_DEPTH
+    NestingTooDeep,
+    /// Rust code called by the virtual machine panicked outside of a foreign
+    /// method, such as in a module loader or an output sink.
+    ///
+    /// A panic in a foreign method instead aborts the calling fiber, and is
+    /// available from [`RuntimeError::panic`] if the fiber is not caught.
+    Panic(Panic),
+}
+
+impl Error {
+    /// Moves the compile errors in `module`, and the frames running its top
+    /// level code, up by `lines`, for source code which was wrapped in extra
+    /// lines before being compiled.
+    pub(crate) fn offset(self, module: &str, lines: usize) -> Self {
+        match self {
+            Error::Compile(errors) => Error::Compile(
+                errors
+                    .into_iter()
+                    .map(|error| {
+                        if error.module() == module {
+                            error.offset(lines)
+                        } else {
+                            error
+                        }
+                    })
+                    .collect(),
+            ),
+            Error::Runtime(error) => Error::Runtime(error.offset(module, lines)),
+            error => error,
+        }
+    }
+
+    /// Moves the outermost frame of a runtime error up by `lines`, if it is in
+    /// `module`, for a function called by the host whose body was wrapped in
+    /// extra lines before being compiled.
+    pub(crate) fn offset_function(self, module: &str, lines: usize) -> Self {
+        match self {
+            Error::Runtime(error) => Error::Runtime(error.offset_outermost(module, lines)),
+            error => error,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -12,3 +93,227 @@ pub struct MismatchedValueError {
     expected: &'static [crate::raw::WrenType],
     found: crate::raw::WrenType,
 }
+
+impl From<std::ffi::NulError> for Error {
+    fn from(_: std::ffi::NulError) -> Self {
+        Error::InteriorNul
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(Arc::new(error))
+    }
+}
+
+impl MismatchedValueError {
+    pub(crate) fn new(
+        expected: &'static [crate::raw::WrenType],
+        found: crate::raw::WrenType,
+    ) -> Self {
+        MismatchedValueError { expected, found }
+    }
+}
+
+/// An error reported by Wren while compiling source code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompileError {
+    module: String,
+    line: usize,
+    message: String,
+}
+
+impl CompileError {
+    pub(crate) fn new(module: String, line: usize, message: String) -> Self {
+        CompileError {
+            module,
+            line,
+            message,
+        }
+    }
+
+    /// The name of the module being compiled.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// The line of the source code on which the error was found, starting
+    /// from one.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// A description of the error, such as `Error at 'x': Expect ')' after
+    /// arguments.`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Moves the error up by `lines`, for source code which was wrapped in
+    /// extra lines before being compiled.
+    pub(crate) fn offset(mut self, lines: usize) -> Self {
+        self.line = self.line.saturating_sub(lines).max(1);
+        self
+    }
+}
+
+/// An error reported by Wren when running code aborted its fiber.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeError {
+    message: String,
+    stack_trace: Vec<StackFrame>,
+    panic: Option<Panic>,
+}
+
+impl RuntimeError {
+    pub(crate) fn new(message: String) -> Self {
+        RuntimeError {
+            message,
+            stack_trace: Vec::new(),
+            panic: None,
+        }
+    }
+
+    /// The error with which the fiber was aborted, such as `Null does not
+    /// implement 'foo'.`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The calls which were running when the error occurred, innermost first.
+    ///
+    /// Calls into Wren's core library are not included.
+    pub fn stack_trace(&self) -> &[StackFrame] {
+        &self.stack_trace
+    }
+
+    /// The panic which aborted the fiber, if the error was caused by a foreign
+    /// method panicking.
+    pub fn panic(&self) -> Option<&Panic> {
+        self.panic.as_ref()
+    }
+
+    pub(crate) fn set_panic(&mut self, panic: Panic) {
+        self.panic = Some(panic);
+    }
+
+    pub(crate) fn push_frame(&mut self, frame: StackFrame) {
+        self.stack_trace.push(frame);
+    }
+
+    /// Moves the frames running the top level code of `module` up by
+    /// `lines`, for source code which was wrapped in extra lines before being
+    /// compiled.
+    pub(crate) fn offset(mut self, module: &str, lines: usize) -> Self {
+        for frame in &mut self.stack_trace {
+            if frame.module == module && frame.function == StackFrame::SCRIPT {
+                frame.line = frame.line.saturating_sub(lines).max(1);
+            }
+        }
+
+        self
+    }
+
+    /// Moves the outermost frame up by `lines` if it is in `module`, for a
+    /// function called by the host whose body was wrapped in extra lines
+    /// before being compiled.
+    pub(crate) fn offset_outermost(mut self, module: &str, lines: usize) -> Self {
+        if let Some(frame) = self.stack_trace.last_mut()
+            && frame.module == module
+        {
+            frame.line = frame.line.saturating_sub(lines).max(1);
+        }
+
+        self
+    }
+}
+
+/// A call in the stack trace of a [`RuntimeError`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StackFrame {
+    module: String,
+    line: usize,
+    function: String,
+}
+
+impl StackFrame {
+    /// The name Wren gives to the top level code of a module.
+    const SCRIPT: &str = "(script)";
+
+    pub(crate) fn new(module: String, line: usize, function: String) -> Self {
+        StackFrame {
+            module,
+            line,
+            function,
+        }
+    }
+
+    /// The name of the module containing the function.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// The line being run in the function, starting from one.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The signature of the function, such as `load(_)`, or `(script)` for
+    /// the top level code of a module.
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+}
+
+/// A panic in Rust code called by the virtual machine, which was caught before
+/// it could unwind into Wren.
+///
+/// Clones of a panic share its payload.
+#[derive(Clone)]
+pub struct Panic {
+    message: String,
+    payload: Arc<Mutex<Option<Box<dyn Any + Send>>>>,
+}
+
+impl Panic {
+    pub(crate) fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => (*message).to_owned(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "Rust code panicked.".to_owned(),
+            },
+        };
+
+        Panic {
+            message,
+            payload: Arc::new(Mutex::new(Some(payload))),
+        }
+    }
+
+    /// The message the code panicked with, or a generic message if the
+    /// payload is not a string.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Takes the payload of the panic, such as to continue it with
+    /// [`std::panic::resume_unwind`].
+    ///
+    /// Returns `None` if the payload has already been taken from this panic
+    /// or one of its clones.
+    pub fn take_payload(&self) -> Option<Box<dyn Any + Send>> {
+        self.payload
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+impl std::fmt::Debug for Panic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Panic")
+            .field("message", &self.message)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,170 @@
+//! Fibers which are driven from Rust.
+
+use crate::{
+    FiberIter, Wren,
+    error::Error,
+    value::{FromWren, Handle, IntoWren, OwnedValue},
+    wren::CachedCallHandle,
+};
+
+/// The state of a [`Fiber`] after it has been resumed.
+#[derive(Debug)]
+pub enum FiberState {
+    /// The fiber called `Fiber.yield`, passing the given value.
+    Yielded(OwnedValue),
+    /// The fiber returned the given value, and cannot be resumed again.
+    Finished(OwnedValue),
+    /// The fiber was aborted with the given error, and cannot be resumed
+    /// again.
+    ///
+    /// This state is only returned by [`Fiber::try_resume`].
+    Errored(OwnedValue),
+}
+
+/// A Wren fiber, which the host can resume like a coroutine.
+///
+/// A fiber can be created from a Wren function with [`Fiber::new`], or taken
+/// from Wren like any other value, such as with [`Wren::get_variable`]. Each
+/// time the fiber is resumed, it runs until it yields, returns or is aborted.
+///
+/// ```
+/// # use wrenlet::{Wren, Fiber, FiberState, value::OwnedValue};
+/// let mut wren = Wren::new();
+///
+/// let source = r#"
+///     var counter = Fiber.new {|start|
+///         Fiber.yield(start + 1)
+///         return start + 2
+///     }
+/// "#;
+/// wren.interpret("main", source).unwrap();
+///
+/// let fiber: Fiber = wren.get_variable("main", "counter").unwrap();
+///
+/// let state = fiber.resume(&mut wren, 1.0).unwrap();
+/// assert!(matches!(state, FiberState::Yielded(OwnedValue::Num(2.0))));
+///
+/// let state = fiber.resume(&mut wren, ()).unwrap();
+/// assert!(matches!(state, FiberState::Finished(OwnedValue::Num(3.0))));
+/// assert!(fiber.is_done(&mut wren).unwrap());
+/// ```
+///
+/// Fibers are not type checked when they are taken from Wren. Using a value
+/// which is not a fiber aborts with a runtime error.
+pub struct Fiber {
+    handle: Handle,
+    call: CachedCallHandle,
+    try_call: CachedCallHandle,
+    is_done: CachedCallHandle,
+    error: CachedCallHandle,
+}
+
+impl Fiber {
+    /// Creates a new fiber which runs `function`.
+    ///
+    /// Returns [`Error::Runtime`] if `function` is not a Wren function.
+    pub fn new<U, M, W>(wren: &mut Wren<U, M, W>, function: &Handle) -> Result<Fiber, Error> {
+        let handle = wren.new_fiber(function)?;
+
+        Ok(Fiber::from_handle(handle))
+    }
+
+    pub(crate) fn from_handle(handle: Handle) -> Fiber {
+        let vm = handle.vm();
+
+        Fiber {
+            call: CachedCallHandle::new(vm, "call(_)"),
+            try_call: CachedCallHandle::new(vm, "try(_)"),
+            is_done: CachedCallHandle::new(vm, "isDone"),
+            error: CachedCallHandle::new(vm, "error"),
+            handle,
+        }
+    }
+
+    /// Resumes the fiber, passing `value` to it.
+    ///
+    /// When a fiber is first resumed, `value` is bound to the parameter of its
+    /// function, if it has one. Otherwise, it is returned from the call to
+    /// `Fiber.yield` which suspended the fiber.
+    ///
+    /// # Errors
+    /// If the fiber is aborted, the error is propagated to the host as
+    /// [`Error::Runtime`], as if the fiber were run by `call`.
+    pub fn resume<U, M, W>(
+        &self,
+        wren: &mut Wren<U, M, W>,
+        value: impl IntoWren,
+    ) -> Result<FiberState, Error> {
+        let result = wren.call(&self.call, &self.handle, (value,))?;
+
+        if self.is_done(wren)? {
+            Ok(FiberState::Finished(result))
+        } else {
+            Ok(FiberState::Yielded(result))
+        }
+    }
+
+    /// Resumes the fiber like [`Fiber::resume`], but catches an error which
+    /// aborts it, as if the fiber were run by `try`.
+    ///
+    /// An aborted fiber returns [`FiberState::Errored`] with its error.
+    pub fn try_resume<U, M, W>(
+        &self,
+        wren: &mut Wren<U, M, W>,
+        value: impl IntoWren,
+    ) -> Result<FiberState, Error> {
+        let result = wren.call(&self.try_call, &self.handle, (value,))?;
+
+        if !self.is_done(wren)? {
+            return Ok(FiberState::Yielded(result));
+        }
+
+        match wren.call(&self.error, &self.handle, ())? {
+            OwnedValue::Null => Ok(FiberState::Finished(result)),
+            error => Ok(FiberState::Errored(error)),
+        }
+    }
+
+    /// Resumes the fiber with `call(_)`, returning a handle to the value it
+    /// yields or returns.
+    pub(crate) fn resume_raw<U, M, W>(
+        &self,
+        wren: &mut Wren<U, M, W>,
+        value: impl IntoWren,
+    ) -> Result<Handle, Error> {
+        wren.call(&self.call, &self.handle, (value,))
+    }
+
+    /// Iterates over the values yielded by the fiber, converting each one to
+    /// `T`.
+    ///
+    /// See [`FiberIter`].
+    pub fn iter<'w, T, U, M, W>(&'w self, wren: &'w mut Wren<U, M, W>) -> FiberIter<'w, T, U, M, W>
+    where
+        T: for<'a> FromWren<'a>,
+    {
+        FiberIter::new(wren, self)
+    }
+
+    /// Returns `true` if the fiber has finished running, either by returning
+    /// or by being aborted.
+    pub fn is_done<U, M, W>(&self, wren: &mut Wren<U, M, W>) -> Result<bool, Error> {
+        wren.call(&self.is_done, &self.handle, ())
+    }
+
+    /// The handle to the underlying Wren fiber object.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Converts the fiber into a handle to the underlying Wren object.
+    pub fn into_handle(self) -> Handle {
+        self.handle
+    }
+}
+
+impl std::fmt::Debug for Fiber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Fiber").field(&self.handle).finish()
+    }
+}
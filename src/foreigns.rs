@@ -1,4 +1,32 @@
-use std::{alloc::Layout, any::TypeId, os::raw::c_void};
+//! Foreign classes, whose instances hold Rust values.
+//!
+//! Every foreign class known to a virtual machine is registered in its
+//! [`WrenHeader`], and bound when Wren compiles the matching `foreign class`
+//! declaration. The data of each instance is a [`ForeignObject`], which
+//! records the header so that its finalizer can update the memory statistics,
+//! and the [`TypeId`] of its value so that it can be read back safely.
+
+use std::{
+    alloc::Layout,
+    any::TypeId,
+    collections::HashMap,
+    ffi::CStr,
+    mem::ManuallyDrop,
+    os::raw::c_void,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    allocator, bridge,
+    error::{Error, Panic},
+    interrupt,
+    raw::WrenPtr,
+    wren::WrenHeader,
+};
+
+/// The alignment Wren guarantees for the data of a foreign object, which
+/// follows a pointer-aligned object header.
+const FOREIGN_ALIGN: usize = std::mem::align_of::<usize>();
 
 #[derive(Debug, Clone, Hash)]
 pub struct ForeignClass {
@@ -6,34 +34,367 @@ pub struct ForeignClass {
     pub name: &'static str,
     pub type_id: TypeId,
     pub layout: Layout,
-    pub drop_fn: extern "C" fn(*mut c_void),
+    pub drop_fn: unsafe extern "C" fn(*mut c_void),
     pub methods: Box<[ForeignMethod]>,
 }
 
 impl ForeignClass {
-    pub fn new_for<T: 'static>(
+    /// Creates a foreign class called `name` in `module`, whose instances
+    /// hold a `T`.
+    ///
+    /// Instances may be finalized by whichever thread frees the virtual
+    /// machine, and so `T` must be [`Send`].
+    pub fn new_for<T: Send + 'static>(
         module: &'static str,
         name: &'static str,
         methods: impl IntoIterator<Item = ForeignMethod>,
     ) -> ForeignClass {
-        extern "C" fn drop_fn<T>(ptr: *mut c_void) {
-            unsafe { std::ptr::drop_in_place::<T>(ptr.cast()) };
-        }
+        let layout = Layout::new::<ForeignObject<T>>();
+
+        assert!(
+            layout.align() <= FOREIGN_ALIGN,
+            "foreign values cannot be aligned to more than {FOREIGN_ALIGN} bytes"
+        );
 
         Self {
             module,
             name,
             type_id: TypeId::of::<T>(),
-            layout: Layout::new::<T>(),
-            drop_fn: drop_fn::<T>,
+            layout,
+            drop_fn: finalize::<T>,
             methods: methods.into_iter().collect(),
         }
     }
+
+    /// Finds the method of this class with the given `signature`.
+    pub fn method(
+        &self,
+        is_static: bool,
+        signature: &CStr,
+    ) -> Option<unsafe extern "C" fn(*mut sys::WrenVM)> {
+        self.methods
+            .iter()
+            .find(|method| {
+                method.is_static == is_static && method.name.as_bytes() == signature.to_bytes()
+            })
+            .map(|method| method.implementation)
+    }
 }
 
 #[derive(Debug, Clone, Hash)]
 pub struct ForeignMethod {
     name: &'static str,
     is_static: bool,
-    implementation: extern "C" fn(*mut sys::WrenVM),
+    implementation: unsafe extern "C" fn(*mut sys::WrenVM),
+}
+
+impl ForeignMethod {
+    /// Creates an instance method with the given `signature`, such as
+    /// `"iterate(_)"`.
+    pub fn new(
+        signature: &'static str,
+        implementation: unsafe extern "C" fn(*mut sys::WrenVM),
+    ) -> ForeignMethod {
+        ForeignMethod {
+            name: signature,
+            is_static: false,
+            implementation,
+        }
+    }
+
+    /// Creates a static method with the given `signature`.
+    pub fn new_static(
+        signature: &'static str,
+        implementation: unsafe extern "C" fn(*mut sys::WrenVM),
+    ) -> ForeignMethod {
+        ForeignMethod {
+            name: signature,
+            is_static: true,
+            implementation,
+        }
+    }
+}
+
+/// The foreign classes registered in every virtual machine.
+pub fn builtin_classes() -> Vec<ForeignClass> {
+    vec![
+        crate::sequence::class(),
+        crate::tasks::class(),
+        crate::scheduler::class(),
+        crate::closure::class(),
+    ]
+}
+
+/// The largest number of async methods which can be registered with a single
+/// virtual machine.
+pub const MAX_ASYNC_METHODS: usize = 64;
+
+/// Finds the registered async method `signature` of the class called `name`
+/// in `module`, returning the trampoline which calls it.
+///
+/// # Safety
+/// The user data of `vm` must point to a valid `WrenHeader`.
+pub unsafe fn find_async_method(
+    vm: &WrenPtr,
+    module: &CStr,
+    name: &CStr,
+    signature: &CStr,
+) -> Option<unsafe extern "C" fn(*mut sys::WrenVM)> {
+    let header = vm.get_user_data::<WrenHeader>();
+
+    let methods = unsafe { &(*header).async_methods };
+
+    let index = methods.iter().position(|method| {
+        method.module.as_bytes() == module.to_bytes()
+            && method.class.as_bytes() == name.to_bytes()
+            && method.signature.as_bytes() == signature.to_bytes()
+    })?;
+
+    Some(ASYNC_TRAMPOLINES[index])
+}
+
+/// Calls the async method registered at index `N`.
+///
+/// Wren gives a foreign method no way to tell which method it was bound as,
+/// so each registered method is bound to its own instance of this function.
+unsafe extern "C" fn async_trampoline<const N: usize>(vm: *mut sys::WrenVM) {
+    unsafe { method(vm, |vm| crate::tasks::call_async_method(vm, N)) };
+}
+
+macro_rules! trampolines {
+    ($($n:literal)*) => {
+        [$(async_trampoline::<$n> as unsafe extern "C" fn(*mut sys::WrenVM)),*]
+    };
+}
+
+static ASYNC_TRAMPOLINES: [unsafe extern "C" fn(*mut sys::WrenVM); MAX_ASYNC_METHODS] = trampolines!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
+
+/// Finds the registered foreign class called `name` in `module`.
+///
+/// # Safety
+/// The user data of `vm` must point to a valid `WrenHeader`.
+pub unsafe fn find_class<'a>(vm: &WrenPtr, module: &CStr, name: &CStr) -> Option<&'a ForeignClass> {
+    let header = vm.get_user_data::<WrenHeader>();
+
+    let classes = unsafe { &(*header).foreign_classes };
+
+    classes.iter().find(|class| {
+        class.module.as_bytes() == module.to_bytes() && class.name.as_bytes() == name.to_bytes()
+    })
+}
+
+/// The data stored in a foreign object.
+#[repr(C)]
+struct ForeignObject<T> {
+    header: *mut WrenHeader,
+    // A function rather than a `TypeId`, which may be more strictly aligned
+    // than the data of a foreign object.
+    type_id: fn() -> TypeId,
+    /// Dropped by the finalizer, or by [`drop_live_objects`] if that runs
+    /// first.
+    value: ManuallyDrop<T>,
+}
+
+/// The foreign objects of a virtual machine whose values have not yet been
+/// dropped, along with the function which drops each value.
+pub type LiveObjects = HashMap<usize, unsafe fn(*mut c_void)>;
+
+/// Stores a new instance of the foreign class in `class_slot`, holding
+/// `value`, in `slot`.
+///
+/// # Safety
+/// - `class_slot` must contain the foreign class registered for `T`,
+/// - both slots must be valid.
+pub unsafe fn new_foreign<T: Send + 'static>(
+    vm: &WrenPtr,
+    slot: usize,
+    class_slot: usize,
+    value: T,
+) {
+    let size = std::mem::size_of::<ForeignObject<T>>();
+
+    let data = unsafe { vm.set_slot_new_foreign::<ForeignObject<T>>(slot, class_slot, size) };
+
+    let header = vm.get_user_data::<WrenHeader>();
+
+    unsafe {
+        data.write(ForeignObject {
+            header,
+            type_id: TypeId::of::<T>,
+            value: ManuallyDrop::new(value),
+        })
+    };
+
+    unsafe { (*header).heap.record_foreign(size) };
+    unsafe {
+        (*header)
+            .live_objects
+            .insert(data as usize, drop_value::<T>)
+    };
+}
+
+/// Drops the value of the foreign object at `data`.
+///
+/// # Safety
+/// `data` must point to a `ForeignObject<T>` whose value has not been
+/// dropped.
+unsafe fn drop_value<T>(data: *mut c_void) {
+    let data = data.cast::<ForeignObject<T>>();
+
+    // Wren gives a finalizer no way to report an error, so a panic while
+    // dropping the value is only reported by the panic hook.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        ManuallyDrop::drop(&mut (*data).value)
+    }));
+}
+
+/// Drops the values of every foreign object of the virtual machine, before it
+/// is freed.
+///
+/// Values such as a [`Closure`] capturing a [`Handle`] keep the virtual
+/// machine allocated, while the virtual machine keeps them alive, so waiting
+/// for the finalizers would leak both. The objects themselves are freed by
+/// Wren as usual.
+///
+/// # Safety
+/// No code may be run by the virtual machine afterwards, as its foreign
+/// objects no longer hold values.
+///
+/// [`Closure`]: crate::value::Closure
+/// [`Handle`]: crate::value::Handle
+pub unsafe fn drop_live_objects(header: *mut WrenHeader) {
+    // The map is taken first, as dropping a value may drop a handle, which
+    // reads the header.
+    let objects = std::mem::take(unsafe { &mut (*header).live_objects });
+
+    for (data, drop_value) in objects {
+        unsafe { drop_value(data as *mut c_void) };
+    }
+}
+
+/// Stores a new instance of the foreign class `class` of the bridge module,
+/// holding `value`, in `slot`. The slot above it is used to hold the class.
+///
+/// The bridge module is loaded when the virtual machine is built, but not if
+/// that failed, such as by exceeding the memory limit, in which case
+/// [`Error::NoSuchModule`] is returned.
+///
+/// # Safety
+/// `class` must be registered for `T`.
+pub unsafe fn new_builtin<T: Send + 'static>(
+    vm: &WrenPtr,
+    slot: usize,
+    class: &CStr,
+    value: T,
+) -> Result<(), Error> {
+    let module = bridge::MODULE_C;
+
+    if !vm.has_module(module) || !unsafe { vm.has_variable(module, class) } {
+        return Err(Error::NoSuchModule);
+    }
+
+    unsafe { vm.ensure_slots(slot + 2) };
+    unsafe { vm.get_variable(module, class, slot + 1) };
+
+    // Safety: The slot above holds the class, which the caller guarantees is
+    // registered for `T`.
+    unsafe { new_foreign(vm, slot, slot + 1, value) };
+
+    Ok(())
+}
+
+/// Gets the value held by the receiver of a foreign method, if it holds a `T`.
+///
+/// The receiver of a method on a foreign class is always an instance of that
+/// class, as foreign classes cannot be inherited from.
+///
+/// # Safety
+/// - Must only be called from a method of a registered foreign class,
+/// - the returned reference must not be used after the object could have been
+///   garbage collected, or while another reference to the value exists.
+pub unsafe fn receiver<'a, T: 'static>(vm: &WrenPtr) -> Option<&'a mut T> {
+    unsafe { get_foreign(vm, 0) }
+}
+
+/// Gets the value held by the foreign object in `slot`, if it holds a `T`.
+///
+/// # Safety
+/// - `slot` must be valid, and contain an instance of a registered foreign
+///   class,
+/// - the returned reference must not be used after the object could have been
+///   garbage collected, or while another reference to the value exists.
+pub unsafe fn get_foreign<'a, T: 'static>(vm: &WrenPtr, slot: usize) -> Option<&'a mut T> {
+    // Only the type identifier is read before it is checked, and it is at the
+    // same offset for every `T`.
+    let data = unsafe { vm.get_slot_foreign::<ForeignObject<T>>(slot) };
+
+    if unsafe { (*data).type_id }() != TypeId::of::<T>() {
+        return None;
+    }
+
+    Some(unsafe { &mut (*data).value })
+}
+
+/// Aborts the current fiber with `message`.
+///
+/// # Safety
+/// Must only be called from a foreign method of `vm`.
+pub unsafe fn abort(vm: &WrenPtr, message: &str) {
+    unsafe { vm.set_slot_bytes(0, message.as_bytes()) };
+    unsafe { vm.abort_fiber(0) };
+}
+
+/// Runs `body` as the implementation of a foreign method of `vm`.
+///
+/// A call to a foreign method is the only point at which the host can abort
+/// the running fiber, so the fiber is aborted instead of running `body` if
+/// it has been interrupted, or if the virtual machine is over its memory
+/// limit.
+///
+/// A panic in `body` cannot unwind into Wren, and so it is caught, and aborts
+/// the current fiber with the panic message instead. Every foreign method is
+/// implemented through this function.
+///
+/// # Safety
+/// Must only be called from a foreign method of `vm`.
+pub unsafe fn method(vm: *mut sys::WrenVM, body: impl FnOnce(&WrenPtr)) {
+    let wren = unsafe { WrenPtr::from_raw(vm) };
+
+    if unsafe { interrupt::abort_if_interrupted(&wren) || allocator::abort_if_over_limit(&wren) } {
+        return;
+    }
+
+    let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| body(&wren))) else {
+        return;
+    };
+
+    let panic = Panic::new(payload);
+
+    unsafe { abort(&wren, panic.message()) };
+
+    // The panic is attached to the runtime error if the fiber is not caught.
+    let header = wren.get_user_data::<WrenHeader>();
+    unsafe { (*header).panic = Some(panic) };
+}
+
+/// The finalizer of a foreign class holding a `T`.
+unsafe extern "C" fn finalize<T>(data: *mut c_void) {
+    let data = data.cast::<ForeignObject<T>>();
+
+    let header = unsafe { (*data).header };
+
+    if unsafe { (*header).live_objects.remove(&(data as usize)) }.is_some() {
+        unsafe { drop_value::<T>(data.cast()) };
+    }
+
+    unsafe {
+        (*header)
+            .heap
+            .release_foreign(std::mem::size_of::<ForeignObject<T>>())
+    };
 }
@@ -0,0 +1,137 @@
+//! Wren functions which are called from Rust.
+
+use crate::{
+    Wren,
+    error::Error,
+    value::{FromWren, Handle, WrenArguments},
+    wren::CachedCallHandle,
+};
+
+/// A Wren function, such as a callback passed to the host by a script.
+///
+/// A `WrenFn` is created from a [`Handle`] with [`WrenFn::new`], which checks
+/// that the value is a function, and records its arity. Like a handle, it
+/// keeps the function alive, and so can be stored by the host and called at
+/// any later time.
+///
+/// ```
+/// # use wrenlet::{Wren, WrenFn, error::Error, value::Handle};
+/// let mut wren = Wren::new();
+///
+/// wren.interpret("main", "var add = Fn.new {|a, b| a + b }").unwrap();
+///
+/// let handle: Handle = wren.get_variable("main", "add").unwrap();
+/// let add = WrenFn::new(&mut wren, handle).unwrap();
+///
+/// assert_eq!(add.arity(), 2);
+///
+/// let sum: f64 = add.call(&mut wren, (1.0, 2.0)).unwrap();
+/// assert_eq!(sum, 3.0);
+///
+/// let result: Result<f64, Error> = add.call(&mut wren, (1.0,));
+/// assert!(matches!(result, Err(Error::ArityMismatch { expected: 2, found: 1 })));
+/// ```
+pub struct WrenFn {
+    handle: Handle,
+    arity: usize,
+    call: CachedCallHandle,
+    /// The module and number of lines by which to move runtime errors in the
+    /// body of a function created by [`Wren::compile_function`].
+    offset: Option<(String, usize)>,
+}
+
+impl WrenFn {
+    /// Checks that `handle` refers to a Wren function, and wraps it.
+    ///
+    /// Returns [`Error::NotAFunction`] if it does not.
+    pub fn new<U, M, W>(wren: &mut Wren<U, M, W>, handle: Handle) -> Result<WrenFn, Error> {
+        let arity = wren.function_arity(&handle)?.ok_or(Error::NotAFunction)?;
+
+        Ok(WrenFn {
+            call: CachedCallHandle::new(handle.vm(), &signature(arity)),
+            handle,
+            arity,
+            offset: None,
+        })
+    }
+
+    /// Moves the lines of runtime errors in the body of the function, which
+    /// was compiled in `module` after `lines` extra lines.
+    pub(crate) fn with_offset(mut self, module: &str, lines: usize) -> WrenFn {
+        self.offset = Some((module.to_owned(), lines));
+        self
+    }
+
+    /// The number of parameters the function takes.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Calls the function with `args`, converting its return value to `T`.
+    ///
+    /// # Errors
+    /// Returns [`Error::ArityMismatch`] without calling the function if the
+    /// number of arguments differs from its arity, and [`Error::Runtime`] if
+    /// the function is aborted.
+    pub fn call<'a, T, U, M, W>(
+        &self,
+        wren: &'a mut Wren<U, M, W>,
+        args: impl WrenArguments,
+    ) -> Result<T, Error>
+    where
+        T: FromWren<'a>,
+    {
+        if args.count() != self.arity {
+            return Err(Error::ArityMismatch {
+                expected: self.arity,
+                found: args.count(),
+            });
+        }
+
+        let result = wren.call(&self.call, &self.handle, args);
+
+        match &self.offset {
+            Some((module, lines)) => result.map_err(|error| error.offset_function(module, *lines)),
+            None => result,
+        }
+    }
+
+    /// The handle to the underlying Wren function object.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Converts the function into a handle to the underlying Wren object.
+    pub fn into_handle(self) -> Handle {
+        self.handle
+    }
+}
+
+impl std::fmt::Debug for WrenFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WrenFn")
+            .field("handle", &self.handle)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+/// The signature of the `call` method taking `arity` arguments.
+fn signature(arity: usize) -> String {
+    match arity {
+        0 => "call()".to_owned(),
+        _ => format!("call({})", vec!["_"; arity].join(",")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        assert_eq!(signature(0), "call()");
+        assert_eq!(signature(1), "call(_)");
+        assert_eq!(signature(3), "call(_,_,_)");
+    }
+}
@@ -0,0 +1,150 @@
+//! Interruption of running scripts from other threads, and step budgets.
+//!
+//! The interpreter calls [`interrupt_fn`] once every [`INTERVAL`] loop
+//! iterations and method calls, which gives the host a chance to abort the
+//! running fiber even if it never calls a foreign method. It is also called
+//! at the next step after an allocation takes the virtual machine over its
+//! memory limit.
+
+use std::{
+    ffi::{CStr, c_char, c_int},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{allocator, error::Error, raw::WrenPtr, wren::WrenHeader};
+
+/// The number of loop iterations and method calls between checks for an
+/// interrupt.
+pub(crate) const INTERVAL: c_int = 1000;
+
+/// A handle which can be used to interrupt the code running in a virtual
+/// machine, from any thread.
+///
+/// An interrupt aborts the running fiber within a thousand loop iterations or
+/// method calls, or at its next call to a foreign method, and the running
+/// [`Wren::interpret`] or [`Wren::call`] returns [`Error::Interrupted`].
+///
+/// The interrupt stays in effect until control returns to the host, so a
+/// fiber which catches the error with `Fiber.try` is aborted again as soon as
+/// it continues. An interrupt which has not taken effect by then is
+/// discarded, so it never affects code which is run afterwards.
+///
+/// [`Wren::interpret`]: crate::Wren::interpret
+/// [`Wren::call`]: crate::Wren::call
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub(crate) fn new() -> InterruptHandle {
+        InterruptHandle::default()
+    }
+
+    /// Requests that the code currently running in the virtual machine stops.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Discards any pending interrupt.
+    pub(crate) fn clear(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+
+    /// Returns `true` if an interrupt is pending.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// The number of steps the code run by a single call into the virtual machine
+/// may take, set by [`Builder::with_step_budget`].
+///
+/// [`Builder::with_step_budget`]: crate::Builder::with_step_budget
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StepBudget {
+    pub limit: Option<u64>,
+    pub remaining: u64,
+}
+
+impl StepBudget {
+    /// Restores the full budget, before more code is run.
+    pub fn reset(&mut self) {
+        self.remaining = self.limit.unwrap_or(0);
+    }
+
+    /// Records that `steps` more steps were taken, returning `true` if the
+    /// budget is exhausted.
+    fn spend(&mut self, steps: u64) -> bool {
+        if self.limit.is_none() {
+            return false;
+        }
+
+        self.remaining = self.remaining.saturating_sub(steps);
+        self.remaining == 0
+    }
+}
+
+/// Returns the message with which to abort the running fiber, if it has been
+/// interrupted or has used up its step budget, recording the error to be
+/// returned to the host.
+///
+/// # Safety
+/// The user data of `vm` must point to a valid `WrenHeader`.
+unsafe fn check(vm: &WrenPtr, steps: u64) -> Option<&'static CStr> {
+    let header = vm.get_user_data::<WrenHeader>();
+
+    let message = if unsafe { (*header).interrupt.is_interrupted() } {
+        c"Interrupted by the host."
+    } else if unsafe { (*header).step_budget.spend(steps) } {
+        c"Step budget exhausted."
+    } else {
+        return None;
+    };
+
+    unsafe { (*header).error = Some(Error::Interrupted) };
+
+    Some(message)
+}
+
+/// Aborts the current fiber if an interrupt is pending, returning `true` if
+/// it was aborted.
+///
+/// # Safety
+/// Must only be called from a foreign method of `vm`, as the fiber is aborted
+/// using slot zero.
+pub(crate) unsafe fn abort_if_interrupted(vm: &WrenPtr) -> bool {
+    let Some(message) = (unsafe { check(vm, 0) }) else {
+        return false;
+    };
+
+    unsafe { vm.set_slot_bytes(0, message.to_bytes()) };
+    unsafe { vm.abort_fiber(0) };
+
+    true
+}
+
+/// The interrupt callback given to Wren.
+///
+/// # Safety
+/// The user data of `vm` must point to a valid `WrenHeader`.
+pub(crate) unsafe extern "C" fn interrupt_fn(vm: *mut sys::WrenVM, steps: c_int) -> *const c_char {
+    let vm = unsafe { WrenPtr::from_raw(vm) };
+
+    // The heap is marked as exhausted, which is reported once control returns
+    // to the host.
+    let message = if unsafe { allocator::is_out_of_memory(&vm) } {
+        c"Out of memory."
+    } else if let Some(message) = unsafe { check(&vm, u64::try_from(steps).unwrap_or(0)) } {
+        message
+    } else {
+        return std::ptr::null();
+    };
+
+    // A fiber which catches the error is aborted again at its next step, for as
+    // long as the cause remains.
+    unsafe { vm.interrupt_soon() };
+
+    message.as_ptr()
+}
@@ -0,0 +1,169 @@
+//! Iteration over Wren sequences and fibers from Rust.
+
+use std::marker::PhantomData;
+
+use crate::{
+    Fiber, Wren,
+    error::Error,
+    value::{FromWren, Handle, IteratorState},
+    wren::CachedCallHandle,
+};
+
+/// An iterator over the elements of a Wren sequence.
+///
+/// Created by [`Wren::iter`]. Elements are produced with the same
+/// `iterate(_)` and `iteratorValue(_)` protocol used by Wren's `for` loops, so
+/// any object implementing it can be iterated, including lists, ranges and
+/// user defined sequences.
+///
+/// If Wren aborts while iterating, or an element cannot be converted to `T`,
+/// the error is returned as the next item, and the iterator then ends.
+pub struct SequenceIter<'w, T, U, M, W> {
+    wren: &'w mut Wren<U, M, W>,
+    sequence: &'w Handle,
+    iterate: CachedCallHandle,
+    iterator_value: CachedCallHandle,
+    state: Option<Handle>,
+    done: bool,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<'w, T, U, M, W> SequenceIter<'w, T, U, M, W> {
+    pub(crate) fn new(wren: &'w mut Wren<U, M, W>, sequence: &'w Handle) -> Self {
+        SequenceIter {
+            iterate: CachedCallHandle::new(sequence.vm(), "iterate(_)"),
+            iterator_value: CachedCallHandle::new(sequence.vm(), "iteratorValue(_)"),
+            wren,
+            sequence,
+            state: None,
+            done: false,
+            item: PhantomData,
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<T>, Error>
+    where
+        T: for<'a> FromWren<'a>,
+    {
+        let IteratorState(state) =
+            self.wren
+                .call(&self.iterate, self.sequence, (self.state.as_ref(),))?;
+
+        self.state = state;
+
+        let Some(state) = &self.state else {
+            return Ok(None);
+        };
+
+        let value = self
+            .wren
+            .call(&self.iterator_value, self.sequence, (state,))?;
+
+        Ok(Some(value))
+    }
+}
+
+impl<T, U, M, W> Iterator for SequenceIter<'_, T, U, M, W>
+where
+    T: for<'a> FromWren<'a>,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.advance().transpose();
+
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+
+        result
+    }
+}
+
+impl<T, U, M, W> std::fmt::Debug for SequenceIter<'_, T, U, M, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SequenceIter")
+            .field("sequence", self.sequence)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator over the values yielded by a Wren fiber.
+///
+/// Created by [`Fiber::iter`]. Each call to [`Iterator::next`] resumes the
+/// fiber with `null`, and returns the value it yields. The iterator ends when
+/// the fiber finishes, and the value it returns is discarded.
+///
+/// If the fiber is aborted, or a value cannot be converted to `T`, the error
+/// is returned as the next item, and the iterator then ends.
+pub struct FiberIter<'w, T, U, M, W> {
+    wren: &'w mut Wren<U, M, W>,
+    fiber: &'w Fiber,
+    done: bool,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<'w, T, U, M, W> FiberIter<'w, T, U, M, W> {
+    pub(crate) fn new(wren: &'w mut Wren<U, M, W>, fiber: &'w Fiber) -> Self {
+        FiberIter {
+            wren,
+            fiber,
+            done: false,
+            item: PhantomData,
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<T>, Error>
+    where
+        T: for<'a> FromWren<'a>,
+    {
+        if self.fiber.is_done(self.wren)? {
+            return Ok(None);
+        }
+
+        // The value is only converted once the fiber is known to have
+        // yielded it, as the value it returns may have any type.
+        let value = self.fiber.resume_raw(self.wren, ())?;
+
+        if self.fiber.is_done(self.wren)? {
+            return Ok(None);
+        }
+
+        self.wren.convert(&value).map(Some)
+    }
+}
+
+impl<T, U, M, W> Iterator for FiberIter<'_, T, U, M, W>
+where
+    T: for<'a> FromWren<'a>,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.advance().transpose();
+
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+
+        result
+    }
+}
+
+impl<T, U, M, W> std::fmt::Debug for FiberIter<'_, T, U, M, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FiberIter")
+            .field("fiber", self.fiber)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,15 +1,36 @@
 #![doc = include_str!("../README.md")]
 #![allow(dead_code)]
 
+pub mod allocator;
 pub mod error;
+pub mod module;
 pub mod value;
 
+mod actor;
+mod bridge;
 mod builder;
+mod closure;
+mod diagnostic;
+mod fiber;
 mod foreigns;
+mod function;
 mod inner;
-mod module;
+mod interrupt;
+mod iter;
+mod pool;
 mod raw;
+mod scheduler;
+mod sequence;
+mod tasks;
 mod wren;
 
+pub use actor::{AsyncWren, Reply};
 pub use builder::Builder;
+pub use diagnostic::Diagnostic;
+pub use fiber::{Fiber, FiberState};
+pub use function::WrenFn;
+pub use interrupt::InterruptHandle;
+pub use iter::{FiberIter, SequenceIter};
+pub use pool::{PooledWren, WrenPool};
+pub use tasks::{BoxFuture, Executor};
 pub use wren::{CallHandle, Wren};
@@ -1,11 +1,24 @@
+//! Loading of the modules imported by scripts.
+
 use std::borrow::Cow;
 
+/// Provides the source code of modules imported by scripts.
 pub trait ModuleLoader {
+    /// Resolves the name of `module` imported from the module `importer`
+    /// into its canonical name.
+    ///
+    /// Returning `None` leaves the name unchanged.
     fn resolve(&self, importer: &str, module: &str) -> Option<Cow<'_, str>>;
 
+    /// Loads the source code of the module with the canonical name `module`.
+    ///
+    /// Returning `None` reports that the module could not be found, unless it
+    /// is one of Wren's optional modules.
     fn load(&self, module: &str) -> Option<Cow<'_, str>>;
 }
 
+/// A module loader which provides no modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Empty;
 
 impl ModuleLoader for Empty {
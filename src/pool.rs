@@ -0,0 +1,275 @@
+//! A pool of reusable virtual machines.
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::{Builder, Wren, allocator::WrenAllocator, error::Error, module::ModuleLoader};
+
+type Factory<U, M, W> = Box<dyn Fn() -> Wren<U, M, W> + Send + Sync>;
+
+type Reset<U, M, W> = Box<dyn Fn(&mut Wren<U, M, W>) + Send + Sync>;
+
+/// The prefix of the module names given to requests by [`PooledWren::module`],
+/// which scripts are not allowed to import.
+pub(crate) const REQUEST_MODULE_PREFIX: &str = "wrenlet/request/";
+
+/// A pool of virtual machines, which are built and prepared ahead of time.
+///
+/// Building a virtual machine and loading the modules a script depends on can
+/// take far longer than running a short script. A pool keeps virtual machines
+/// around after they are used, so that this work is only done once for each
+/// of them.
+///
+/// Virtual machines are built by a factory returning a [`Builder`], and then
+/// prepared by running a warm-up script. A virtual machine is not returned to
+/// the pool if the code it ran was aborted, if a fiber is still waiting on a
+/// task or timer, or if it uses more memory than the threshold set by
+/// [`WrenPool::with_memory_threshold`].
+///
+/// # Isolation
+/// Wren cannot unload a module, so every module a request defines remains in
+/// the virtual machine for the next request. Each request should therefore
+/// run its code in the module named by [`PooledWren::module`], which is
+/// unique to that request, and which no other request can import. The memory
+/// held by the variables of old request modules is only released once the
+/// virtual machine is discarded, such as by exceeding the memory threshold.
+///
+/// Changes a request makes to the warm-up module, and to the user data, are
+/// seen by later requests. The user data can be reset with
+/// [`WrenPool::with_reset`].
+///
+/// ```
+/// # use wrenlet::{Wren, WrenPool};
+/// let pool = WrenPool::new(Wren::builder)
+///     .with_warm_up("prelude", "var greeting = \"Hello\"");
+///
+/// for _ in 0..2 {
+///     let mut wren = pool.get().unwrap();
+///     let module = wren.module().to_owned();
+///
+///     let source = "import \"prelude\" for greeting\nvar message = greeting";
+///     wren.interpret(&module, source).unwrap();
+/// }
+///
+/// assert_eq!(pool.idle_count(), 1);
+/// ```
+pub struct WrenPool<U, M, W> {
+    factory: Factory<U, M, W>,
+    warm_up: Option<(String, String)>,
+    memory_threshold: Option<usize>,
+    max_idle: Option<usize>,
+    reset: Option<Reset<U, M, W>>,
+    idle: Mutex<Vec<Wren<U, M, W>>>,
+    requests: AtomicU64,
+}
+
+impl<U, M, W> WrenPool<U, M, W> {
+    /// Creates a new pool, which builds its virtual machines with the builders
+    /// returned by `factory`.
+    pub fn new<A, F>(factory: F) -> WrenPool<U, M, W>
+    where
+        F: Fn() -> Builder<U, M, W, A> + Send + Sync + 'static,
+        M: ModuleLoader,
+        W: std::io::Write,
+        A: WrenAllocator + 'static,
+    {
+        WrenPool {
+            factory: Box::new(move || factory().build()),
+            warm_up: None,
+            memory_threshold: None,
+            max_idle: None,
+            reset: None,
+            idle: Mutex::new(Vec::new()),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the script interpreted in `module` by each new virtual machine,
+    /// before it is first handed out.
+    pub fn with_warm_up(mut self, module: &str, source: &str) -> Self {
+        self.warm_up = Some((module.to_owned(), source.to_owned()));
+        self
+    }
+
+    /// Sets the number of bytes a virtual machine may have allocated after a
+    /// garbage collection for it to be returned to the pool.
+    pub fn with_memory_threshold(mut self, bytes: usize) -> Self {
+        self.memory_threshold = Some(bytes);
+        self
+    }
+
+    /// Sets the largest number of idle virtual machines kept by the pool.
+    pub fn with_max_idle(mut self, count: usize) -> Self {
+        self.max_idle = Some(count);
+        self
+    }
+
+    /// Sets a function which is run on each virtual machine before it is
+    /// returned to the pool, such as to reset its user data.
+    pub fn with_reset<F>(mut self, reset: F) -> Self
+    where
+        F: Fn(&mut Wren<U, M, W>) + Send + Sync + 'static,
+    {
+        self.reset = Some(Box::new(reset));
+        self
+    }
+
+    /// Builds and warms up `count` virtual machines, and adds them to the
+    /// pool.
+    pub fn fill(&self, count: usize) -> Result<(), Error> {
+        for _ in 0..count {
+            let wren = self.create()?;
+
+            self.idle().push(wren);
+        }
+
+        Ok(())
+    }
+
+    /// The number of idle virtual machines in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle().len()
+    }
+
+    /// Takes a virtual machine from the pool, building a new one if none are
+    /// idle.
+    ///
+    /// Returns an error if a new virtual machine fails to run the warm-up
+    /// script.
+    pub fn get(&self) -> Result<PooledWren<'_, U, M, W>, Error> {
+        let wren = match self.idle().pop() {
+            Some(wren) => wren,
+            None => self.create()?,
+        };
+
+        let request = self.requests.fetch_add(1, Ordering::Relaxed);
+
+        Ok(PooledWren {
+            pool: self,
+            wren: Some(wren),
+            module: format!("{REQUEST_MODULE_PREFIX}{request}"),
+        })
+    }
+
+    fn create(&self) -> Result<Wren<U, M, W>, Error> {
+        let mut wren = (self.factory)();
+
+        if let Some((module, source)) = &self.warm_up {
+            wren.interpret(module, source)?;
+        }
+
+        Ok(wren)
+    }
+
+    /// Returns `wren` to the pool, unless it should be discarded.
+    fn recycle(&self, mut wren: Wren<U, M, W>) {
+        if wren.has_faulted() || wren.has_pending_fibers() {
+            return;
+        }
+
+        if let Some(reset) = &self.reset {
+            reset(&mut wren);
+        }
+
+        if let Some(threshold) = self.memory_threshold
+            && wren.memory_stats().allocated > threshold
+        {
+            wren.collect_garbage();
+
+            if wren.memory_stats().allocated > threshold {
+                return;
+            }
+        }
+
+        let mut idle = self.idle();
+
+        if self.max_idle.is_none_or(|max| idle.len() < max) {
+            idle.push(wren);
+        }
+    }
+
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Wren<U, M, W>>> {
+        // A panic while the lock is held cannot leave the list inconsistent.
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<U, M, W> std::fmt::Debug for WrenPool<U, M, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WrenPool")
+            .field("warm_up", &self.warm_up)
+            .field("memory_threshold", &self.memory_threshold)
+            .field("max_idle", &self.max_idle)
+            .field("idle", &self.idle_count())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A virtual machine taken from a [`WrenPool`].
+///
+/// The virtual machine is returned to the pool when this guard is dropped.
+pub struct PooledWren<'p, U, M, W> {
+    pool: &'p WrenPool<U, M, W>,
+    wren: Option<Wren<U, M, W>>,
+    module: String,
+}
+
+impl<U, M, W> PooledWren<'_, U, M, W> {
+    /// The name of a module unique to this request, in which its code should
+    /// be run.
+    ///
+    /// The module does not exist until code is interpreted in it, and it
+    /// cannot be imported by other modules.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// Drops the virtual machine instead of returning it to the pool.
+    pub fn discard(mut self) {
+        self.wren = None;
+    }
+
+    /// Removes the virtual machine from the pool, and returns it.
+    pub fn into_inner(mut self) -> Wren<U, M, W> {
+        self.wren
+            .take()
+            .expect("pooled virtual machine already taken")
+    }
+}
+
+impl<U, M, W> Deref for PooledWren<'_, U, M, W> {
+    type Target = Wren<U, M, W>;
+
+    fn deref(&self) -> &Self::Target {
+        self.wren
+            .as_ref()
+            .expect("pooled virtual machine already taken")
+    }
+}
+
+impl<U, M, W> DerefMut for PooledWren<'_, U, M, W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.wren
+            .as_mut()
+            .expect("pooled virtual machine already taken")
+    }
+}
+
+impl<U: std::fmt::Debug, M, W> std::fmt::Debug for PooledWren<'_, U, M, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PooledWren").field(&self.wren).finish()
+    }
+}
+
+impl<U, M, W> Drop for PooledWren<'_, U, M, W> {
+    fn drop(&mut self) {
+        if let Some(wren) = self.wren.take() {
+            self.pool.recycle(wren);
+        }
+    }
+}
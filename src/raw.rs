@@ -78,6 +78,20 @@ impl WrenPtr {
         unsafe { sys::wrenCollectGarbage(self.0.as_ptr()) };
     }
 
+    /// The number of garbage collections the VM has run.
+    pub fn collection_count(&self) -> usize {
+        unsafe { sys::wrenGetCollectionCount(self.0.as_ptr()) }
+    }
+
+    /// Makes the interpreter call its interrupt callback at the next loop
+    /// iteration or method call.
+    ///
+    /// # Safety
+    /// Must only be called by the thread running the VM.
+    pub unsafe fn interrupt_soon(&self) {
+        unsafe { sys::wrenInterruptSoon(self.0.as_ptr()) };
+    }
+
     /// Runs a string of Wren code in a new fiber in the context of the given module.
     pub unsafe fn interpret(&self, module: &CStr, source: &CStr) -> Result<(), InterpretError> {
         let result =
@@ -359,7 +373,9 @@ impl WrenPtr {
 
     /// Stores a new empty map in `slot`.
     pub unsafe fn set_slot_new_map(&self, slot: usize) {
-        todo!()
+        let slot = i32::try_from(slot).expect(SLOT_FROM_USIZE_MSG);
+
+        unsafe { sys::wrenSetSlotNewMap(self.0.as_ptr(), slot) };
     }
 
     /// Creates a new uninitialised instance foreign class in `class_slot` in
@@ -465,3 +481,7 @@ impl WrenPtr {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct HandlePtr(NonNull<sys::WrenHandle>);
+
+// Safety: A handle pointer is only dereferenced by the virtual machine, which
+// is only used by one thread at a time.
+unsafe impl Send for HandlePtr {}
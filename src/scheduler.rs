@@ -0,0 +1,171 @@
+//! The `scheduler` and `timer` modules, which queue fibers on a clock driven
+//! by the host.
+//!
+//! Both modules are provided to every virtual machine, unless its module
+//! loader defines modules of the same names. Fibers waiting on a timer are
+//! recorded in the [`WrenHeader`], and resumed by [`Wren::tick`] or
+//! [`Wren::run_until_idle`] once the clock passes their deadline.
+//!
+//! [`Wren::tick`]: crate::Wren::tick
+//! [`Wren::run_until_idle`]: crate::Wren::run_until_idle
+
+use std::time::Duration;
+
+use crate::{
+    bridge,
+    foreigns::{self, ForeignClass, ForeignMethod},
+    raw::{WrenPtr, WrenType},
+    value::{FromWren, Handle},
+    wren::WrenHeader,
+};
+
+/// The source code of the `scheduler` module.
+const SCHEDULER_SOURCE: &str = r#"
+import "wrenlet" for Clock
+
+class Scheduler {
+  static add(callable) {
+    Clock.schedule_(Fiber.new { callable.call() }, 0)
+  }
+}
+"#;
+
+/// The source code of the `timer` module.
+const TIMER_SOURCE: &str = r#"
+import "wrenlet" for Clock
+
+class Timer {
+  static sleep(milliseconds) {
+    if (!(milliseconds is Num)) Fiber.abort("Milliseconds must be a number.")
+    if (milliseconds < 0) Fiber.abort("Milliseconds cannot be negative.")
+
+    Clock.schedule_(Fiber.current, milliseconds)
+    return Fiber.suspend()
+  }
+}
+"#;
+
+/// Returns the source code of the built in module called `name`, if there is
+/// one.
+pub fn builtin_module(name: &str) -> Option<&'static str> {
+    match name {
+        "scheduler" => Some(SCHEDULER_SOURCE),
+        "timer" => Some(TIMER_SOURCE),
+        _ => None,
+    }
+}
+
+/// The fibers of a virtual machine which are waiting for a deadline, and the
+/// clock they are measured against.
+#[derive(Default)]
+pub(crate) struct Timers {
+    now: Duration,
+    next_id: u64,
+    pending: Vec<Timer>,
+}
+
+struct Timer {
+    deadline: Duration,
+    /// Orders timers with the same deadline by when they were scheduled.
+    id: u64,
+    fiber: Handle,
+}
+
+impl Timers {
+    /// The current time of the clock.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Moves the clock forward to `now`. The clock never moves backwards.
+    pub fn advance(&mut self, now: Duration) {
+        self.now = self.now.max(now);
+    }
+
+    /// The identifier which will be given to the next timer scheduled.
+    pub fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    /// The earliest deadline of any pending timer.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.pending.iter().map(|timer| timer.deadline).min()
+    }
+
+    fn schedule(&mut self, fiber: Handle, delay: Duration) {
+        self.pending.push(Timer {
+            deadline: self.now + delay,
+            id: self.next_id,
+            fiber,
+        });
+
+        self.next_id += 1;
+    }
+
+    /// Removes the earliest timer which has expired, and was scheduled before
+    /// the timer `before`, returning its fiber.
+    pub fn pop_expired(&mut self, before: u64) -> Option<Handle> {
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, timer)| timer.deadline <= self.now && timer.id < before)
+            .min_by_key(|(_, timer)| (timer.deadline, timer.id))
+            .map(|(index, _)| index)?;
+
+        Some(self.pending.remove(index).fiber)
+    }
+
+    /// The number of pending timers.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Removes every pending timer, returning their fibers.
+    pub fn clear(&mut self) -> Vec<Handle> {
+        self.pending.drain(..).map(|timer| timer.fiber).collect()
+    }
+}
+
+/// The foreign class registered for `Clock`, which has no instances.
+pub(crate) fn class() -> ForeignClass {
+    ForeignClass::new_for::<()>(
+        bridge::MODULE,
+        bridge::name(bridge::CLOCK),
+        [ForeignMethod::new_static("schedule_(_,_)", schedule)],
+    )
+}
+
+/// Implements `Clock.schedule_(_,_)`, which resumes a fiber once a number of
+/// milliseconds have passed.
+unsafe extern "C" fn schedule(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        // The modules check that the delay is a non-negative number, but not
+        // that it is finite.
+        let delay = match unsafe { wren.get_slot_type(2) } {
+            WrenType::Num => {
+                Duration::try_from_secs_f64(unsafe { wren.get_slot_double(2) } / 1000.0)
+            }
+            _ => return,
+        };
+
+        let Ok(delay) = delay else {
+            unsafe { foreigns::abort(wren, "Milliseconds must be finite.") };
+            return;
+        };
+
+        let Ok(fiber) = Handle::get_value(wren, 1) else {
+            return;
+        };
+
+        let header = wren.get_user_data::<WrenHeader>();
+
+        // Safety: The timers are only accessed by the thread running the virtual
+        // machine, and no other reference to them is held while it runs.
+        unsafe { (*header).timers.schedule(fiber, delay) };
+
+        unsafe { wren.set_slot_null(0) };
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
@@ -0,0 +1,160 @@
+//! Rust iterators exposed to Wren as sequences.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{
+    bridge,
+    error::Error,
+    foreigns::{self, ForeignClass, ForeignMethod},
+    raw::{WrenPtr, WrenType},
+    value::{IntoWren, OwnedValue},
+};
+
+type BoxedIterator = Box<dyn Iterator<Item = OwnedValue> + Send>;
+
+/// A lazily evaluated Rust iterator, which Wren code can use like any other
+/// `Sequence`.
+///
+/// When passed to Wren, a sequence becomes an instance of the `Iterator` class
+/// of the `wrenlet` module, which implements the `iterate(_)` and
+/// `iteratorValue(_)` protocol, and so can be used with `for` loops, `map`,
+/// `where` and the other methods of `Sequence`. Elements are only produced as
+/// Wren asks for them.
+///
+/// Like the Rust iterator it wraps, a sequence can only be iterated once. Each
+/// time a sequence is passed to Wren, the new Wren object shares the same
+/// underlying iterator.
+///
+/// An iterator may yield, or capture, a [`Handle`] into the same virtual
+/// machine. The copies of the sequence held by Wren are dropped along with the
+/// [`Wren`], so that such a handle does not keep the virtual machine allocated
+/// forever, but a copy kept by Rust holds on to the virtual machine until it
+/// is dropped.
+///
+/// [`Handle`]: crate::value::Handle
+/// [`Wren`]: crate::Wren
+///
+/// ```
+/// # use wrenlet::{Wren, value::Sequence};
+/// let mut wren = Wren::new();
+///
+/// wren.interpret("main", "var squares = null").unwrap();
+/// wren.set_variable("main", "squares", Sequence::new((1..).map(|i| (i * i) as f64)))
+///     .unwrap();
+///
+/// wren.interpret("main", "var total = squares.take(3).reduce {|a, b| a + b }")
+///     .unwrap();
+///
+/// assert_eq!(wren.get_variable::<f64>("main", "total").unwrap(), 14.0);
+/// ```
+#[derive(Clone)]
+pub struct Sequence(Arc<Mutex<BoxedIterator>>);
+
+impl Sequence {
+    /// Creates a sequence from the given iterator.
+    pub fn new<I>(iter: I) -> Sequence
+    where
+        I: IntoIterator,
+        I::Item: Into<OwnedValue> + 'static,
+        I::IntoIter: Send + 'static,
+    {
+        Sequence(Arc::new(Mutex::new(Box::new(
+            iter.into_iter().map(Into::into),
+        ))))
+    }
+
+    /// Places a new Wren object sharing this sequence's iterator in `slot`.
+    pub(crate) fn put(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        let object = IteratorObject {
+            iter: self.0.clone(),
+            current: None,
+        };
+
+        // Safety: The class is registered for `IteratorObject`.
+        unsafe { foreigns::new_builtin(wren, slot, bridge::ITERATOR, object) }
+    }
+}
+
+impl std::fmt::Debug for Sequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sequence").finish_non_exhaustive()
+    }
+}
+
+/// The data of an instance of the `Iterator` class.
+struct IteratorObject {
+    iter: Arc<Mutex<BoxedIterator>>,
+    /// The element most recently produced by `iterate(_)`.
+    current: Option<OwnedValue>,
+}
+
+/// The foreign class registered for [`IteratorObject`].
+pub(crate) fn class() -> ForeignClass {
+    ForeignClass::new_for::<IteratorObject>(
+        bridge::MODULE,
+        bridge::name(bridge::ITERATOR),
+        [
+            ForeignMethod::new("iterate(_)", iterate),
+            ForeignMethod::new("iteratorValue(_)", iterator_value),
+        ],
+    )
+}
+
+/// Implements `Iterator.iterate(_)`.
+///
+/// Advances the Rust iterator, and returns the number of elements produced so
+/// far, or `false` once the iterator is exhausted.
+unsafe extern "C" fn iterate(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        let Some(object) = (unsafe { foreigns::receiver::<IteratorObject>(wren) }) else {
+            return;
+        };
+
+        let index = match unsafe { wren.get_slot_type(1) } {
+            WrenType::Num => (unsafe { wren.get_slot_double(1) }) + 1.0,
+            _ => 0.0,
+        };
+
+        object.current = object
+            .iter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next();
+
+        if object.current.is_some() {
+            unsafe { wren.set_slot_double(0, index) };
+        } else {
+            unsafe { wren.set_slot_bool(0, false) };
+        }
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
+
+/// Implements `Iterator.iteratorValue(_)`.
+///
+/// Returns the element produced by the last call to `iterate(_)`.
+unsafe extern "C" fn iterator_value(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        let Some(object) = (unsafe { foreigns::receiver::<IteratorObject>(wren) }) else {
+            return;
+        };
+
+        // Placing a list in the reciever's slot may collect garbage, so the
+        // object is kept alive by a handle until the element has been copied.
+        let reciever = unsafe { wren.get_slot_handle(0) };
+
+        let result = match &object.current {
+            Some(value) => value.put_value(wren, 0),
+            None => ().put_value(wren, 0),
+        };
+
+        if result.is_err() {
+            unsafe { wren.set_slot_null(0) };
+        }
+
+        unsafe { wren.release_handle(reciever) };
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
@@ -0,0 +1,327 @@
+//! Foreign methods implemented by Rust futures.
+//!
+//! An async method returns a `Task` object to Wren as soon as it is called,
+//! and its future is handed to the host's [`Executor`]. Awaiting the task
+//! from Wren parks the calling fiber, and suspends the virtual machine. Once
+//! the future completes, its result is queued, and the fiber is resumed with
+//! it the next time the host calls [`Wren::resume_tasks`] or
+//! [`Wren::wait_for_tasks`].
+//!
+//! [`Wren::resume_tasks`]: crate::Wren::resume_tasks
+//! [`Wren::wait_for_tasks`]: crate::Wren::wait_for_tasks
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    bridge,
+    foreigns::{self, ForeignClass, ForeignMethod},
+    raw::WrenPtr,
+    value::{FromWren, Handle, OwnedValue},
+    wren::WrenHeader,
+};
+
+/// A future which can be run by an [`Executor`].
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The result of an async method, either a value or an error message with
+/// which the awaiting fiber is aborted.
+pub(crate) type TaskResult = Result<OwnedValue, String>;
+
+/// The function implementing an async method.
+pub(crate) type AsyncFn = Arc<dyn Fn(Vec<OwnedValue>) -> BoxFuture<TaskResult> + Send + Sync>;
+
+/// Runs the futures created by async methods.
+///
+/// This is implemented for any function which spawns a future, such as a
+/// closure calling `tokio::spawn`.
+pub trait Executor: Send + Sync {
+    /// Runs `future` to completion in the background.
+    fn spawn(&self, future: BoxFuture<()>);
+}
+
+impl<F> Executor for F
+where
+    F: Fn(BoxFuture<()>) + Send + Sync,
+{
+    fn spawn(&self, future: BoxFuture<()>) {
+        self(future)
+    }
+}
+
+/// An async method registered with [`Wren::register_async_method`].
+///
+/// [`Wren::register_async_method`]: crate::Wren::register_async_method
+pub(crate) struct AsyncMethod {
+    pub module: String,
+    pub class: String,
+    pub signature: String,
+    pub function: AsyncFn,
+}
+
+/// The tasks of a virtual machine, shared with the futures which complete
+/// them.
+#[derive(Default)]
+pub(crate) struct Tasks {
+    queue: Mutex<TaskQueue>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct TaskQueue {
+    next_id: u64,
+    /// The number of tasks whose futures have not completed.
+    running: usize,
+    /// Tasks whose `Task` objects have not been finalized.
+    live: HashSet<u64>,
+    completed: HashMap<u64, TaskResult>,
+    /// Fibers waiting for a task to complete.
+    parked: HashMap<u64, Handle>,
+}
+
+impl TaskQueue {
+    /// Returns `true` if no parked fiber can be resumed, but a task which
+    /// could resume one is still running.
+    fn is_blocked(&mut self) -> bool {
+        self.running > 0
+            && self
+                .parked
+                .keys()
+                .all(|id| !self.completed.contains_key(id))
+    }
+}
+
+impl Tasks {
+    fn queue(&self) -> MutexGuard<'_, TaskQueue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Creates a new task, returning its identifier.
+    fn start(&self) -> u64 {
+        let mut queue = self.queue();
+
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.running += 1;
+        queue.live.insert(id);
+
+        id
+    }
+
+    fn complete(&self, id: u64, result: TaskResult) {
+        let mut queue = self.queue();
+
+        queue.running -= 1;
+
+        // The result of a task which can no longer be awaited is dropped.
+        let unused = if queue.live.contains(&id) {
+            queue.completed.insert(id, result);
+            None
+        } else {
+            Some(result)
+        };
+
+        self.changed.notify_all();
+
+        drop(queue);
+        drop(unused);
+    }
+
+    /// Takes a fiber whose task has completed, along with the result of that
+    /// task.
+    pub fn take_ready(&self) -> Option<(Handle, TaskResult)> {
+        let mut queue = self.queue();
+
+        let id = *queue
+            .parked
+            .keys()
+            .find(|id| queue.completed.contains_key(id))?;
+
+        let fiber = queue.parked.remove(&id)?;
+        let result = queue.completed.remove(&id)?;
+
+        Some((fiber, result))
+    }
+
+    /// Returns `true` if a parked fiber is waiting for a task which is still
+    /// running.
+    pub fn has_waiting(&self) -> bool {
+        let queue = self.queue();
+
+        queue
+            .parked
+            .keys()
+            .any(|id| !queue.completed.contains_key(id))
+    }
+
+    /// Returns `true` if any fiber is parked, awaiting a task.
+    pub fn has_parked(&self) -> bool {
+        !self.queue().parked.is_empty()
+    }
+
+    /// The number of fibers parked, awaiting a task.
+    pub fn parked(&self) -> usize {
+        self.queue().parked.len()
+    }
+
+    /// Blocks until a task completes, if any are running.
+    pub fn wait(&self) {
+        let queue = self.queue();
+
+        let _queue = self
+            .changed
+            .wait_while(queue, TaskQueue::is_blocked)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Blocks until a task completes, if any are running, or `timeout` has
+    /// passed.
+    pub fn wait_timeout(&self, timeout: Duration) {
+        let queue = self.queue();
+
+        let _queue = self
+            .changed
+            .wait_timeout_while(queue, timeout, TaskQueue::is_blocked)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Drops every parked fiber and completed result, so that their handles
+    /// no longer keep the virtual machine alive.
+    pub fn clear(&self) {
+        let (parked, completed) = {
+            let mut queue = self.queue();
+
+            (
+                std::mem::take(&mut queue.parked),
+                std::mem::take(&mut queue.completed),
+            )
+        };
+
+        // Dropping a handle may free the virtual machine, along with these
+        // tasks, so this must happen after the lock is released.
+        drop(parked);
+        drop(completed);
+    }
+
+    /// Forgets the task `id`, whose `Task` object has been finalized.
+    fn forget(&self, id: u64) {
+        let result = {
+            let mut queue = self.queue();
+
+            queue.live.remove(&id);
+            queue.completed.remove(&id)
+        };
+
+        drop(result);
+    }
+}
+
+/// The data of an instance of the `Task` class.
+struct TaskObject {
+    tasks: Arc<Tasks>,
+    id: u64,
+    awaited: bool,
+}
+
+impl Drop for TaskObject {
+    fn drop(&mut self) {
+        self.tasks.forget(self.id);
+    }
+}
+
+/// The foreign class registered for [`TaskObject`].
+pub(crate) fn class() -> ForeignClass {
+    ForeignClass::new_for::<TaskObject>(
+        bridge::MODULE,
+        bridge::name(bridge::TASK),
+        [ForeignMethod::new("park_(_)", park)],
+    )
+}
+
+/// Implements `Task.park_(_)`, which records the fiber awaiting the task.
+unsafe extern "C" fn park(vm: *mut sys::WrenVM) {
+    let body = |wren: &WrenPtr| {
+        let Some(task) = (unsafe { foreigns::receiver::<TaskObject>(wren) }) else {
+            return;
+        };
+
+        if task.awaited {
+            unsafe { foreigns::abort(wren, "Task has already been awaited.") };
+            return;
+        }
+
+        let Ok(fiber) = Handle::get_value(wren, 1) else {
+            return;
+        };
+
+        task.awaited = true;
+        task.tasks.queue().parked.insert(task.id, fiber);
+    };
+
+    unsafe { foreigns::method(vm, body) };
+}
+
+/// Calls the async method registered at `index`, placing a new `Task` object
+/// in slot zero.
+///
+/// # Safety
+/// Must only be called as a foreign method of `vm`.
+pub(crate) unsafe fn call_async_method(vm: &WrenPtr, index: usize) {
+    let header = vm.get_user_data::<WrenHeader>();
+
+    // Safety: Methods are only registered before they are bound, and are
+    // never removed.
+    let methods = unsafe { &(*header).async_methods };
+    let function = methods[index].function.clone();
+
+    let Some(executor) = (unsafe { (*header).executor.clone() }) else {
+        unsafe { foreigns::abort(vm, "No executor has been set for async methods.") };
+        return;
+    };
+
+    let tasks = unsafe { (*header).tasks.clone() };
+
+    let mut arguments = Vec::new();
+
+    // Arguments are read from last to first, as reading a list uses the slot
+    // above it.
+    for slot in (1..vm.get_slot_count()).rev() {
+        match OwnedValue::get_value(vm, slot) {
+            Ok(value) => arguments.push(value),
+            Err(_) => {
+                unsafe { foreigns::abort(vm, "Invalid argument to async method.") };
+                return;
+            }
+        }
+    }
+
+    arguments.reverse();
+
+    let id = tasks.start();
+
+    let object = TaskObject {
+        tasks: tasks.clone(),
+        id,
+        awaited: false,
+    };
+
+    // Safety: The class is registered for `TaskObject`. If it is missing, the
+    // object is dropped, which forgets the task.
+    if unsafe { foreigns::new_builtin(vm, 0, bridge::TASK, object) }.is_err() {
+        unsafe { foreigns::abort(vm, "The wrenlet module is not loaded.") };
+        return;
+    }
+
+    let future = function(arguments);
+
+    executor.spawn(Box::pin(async move {
+        let result = future.await;
+
+        tasks.complete(id, result);
+    }));
+}
@@ -5,8 +5,14 @@
 
 use ::sealed::sealed;
 
+pub use crate::{
+    closure::{Closure, IntoClosure},
+    sequence::Sequence,
+};
+
 use crate::{
-    error::Error,
+    Fiber, WrenFn,
+    error::{Error, MismatchedValueError},
     raw::{HandlePtr, WrenPtr, WrenType},
     wren::WrenHeader,
 };
@@ -21,13 +27,196 @@ pub enum Value<'s> {
     String(&'s [u8]),
 }
 
+/// The number of lists which may be nested inside one another in an
+/// [`OwnedValue`].
+pub const MAX_LIST_DEPTH: usize = 64;
+
+/// A value copied out of a virtual machine, which does not borrow from it.
+///
+/// Lists are copied element by element. Wren provides no way to enumerate the
+/// keys of a map, so maps, along with every other kind of object, are held
+/// through a [`Handle`].
+///
+/// Copying lists nested more than [`MAX_LIST_DEPTH`] deep, such as a list
+/// which contains itself, fails with [`Error::NestingTooDeep`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub enum OwnedValue {
+    #[default]
+    Null,
+    Bool(bool),
+    Num(f64),
+    String(Vec<u8>),
+    List(Vec<OwnedValue>),
+    Handle(Handle),
+}
+
+#[sealed]
+impl FromWren<'_> for OwnedValue {
+    fn get_value(wren: &WrenPtr, slot: usize) -> Result<Self, Error> {
+        OwnedValue::get_nested(wren, slot, 0)
+    }
+}
+
+impl OwnedValue {
+    /// Copies the value in `slot`, which is an element of `depth` nested lists.
+    fn get_nested(wren: &WrenPtr, slot: usize, depth: usize) -> Result<Self, Error> {
+        if slot >= wren.get_slot_count() {
+            return Ok(OwnedValue::Null);
+        }
+
+        match unsafe { wren.get_slot_type(slot) } {
+            WrenType::Null => Ok(OwnedValue::Null),
+            WrenType::Bool => Ok(OwnedValue::Bool(unsafe { wren.get_slot_bool(slot) })),
+            WrenType::Num => Ok(OwnedValue::Num(unsafe { wren.get_slot_double(slot) })),
+            WrenType::String => {
+                let value = unsafe { &*wren.get_slot_string(slot) };
+
+                Ok(OwnedValue::String(value.to_vec()))
+            }
+            WrenType::List => {
+                if depth == MAX_LIST_DEPTH {
+                    return Err(Error::NestingTooDeep);
+                }
+
+                // Elements are read into the slot above the list, and nested
+                // lists use the slots above that.
+                unsafe { wren.ensure_slots(slot + 2) };
+
+                let count = unsafe { wren.get_list_count(slot) };
+
+                let mut list = Vec::with_capacity(count);
+
+                for index in 0..count {
+                    let index = isize::try_from(index).unwrap();
+
+                    unsafe { wren.get_list_element(slot, index, slot + 1) };
+
+                    list.push(OwnedValue::get_nested(wren, slot + 1, depth + 1)?);
+                }
+
+                Ok(OwnedValue::List(list))
+            }
+            WrenType::Map | WrenType::Unknown | WrenType::Foreign => {
+                Ok(OwnedValue::Handle(Handle::get_value(wren, slot)?))
+            }
+        }
+    }
+}
+
+impl From<bool> for OwnedValue {
+    fn from(value: bool) -> Self {
+        OwnedValue::Bool(value)
+    }
+}
+
+impl From<f64> for OwnedValue {
+    fn from(value: f64) -> Self {
+        OwnedValue::Num(value)
+    }
+}
+
+impl From<String> for OwnedValue {
+    fn from(value: String) -> Self {
+        OwnedValue::String(value.into_bytes())
+    }
+}
+
+impl From<&str> for OwnedValue {
+    fn from(value: &str) -> Self {
+        OwnedValue::String(value.as_bytes().to_vec())
+    }
+}
+
+impl<T: Into<OwnedValue>> From<Vec<T>> for OwnedValue {
+    fn from(value: Vec<T>) -> Self {
+        OwnedValue::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<Handle> for OwnedValue {
+    fn from(value: Handle) -> Self {
+        OwnedValue::Handle(value)
+    }
+}
+
+#[sealed]
+impl IntoWren for Sequence {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        self.put(wren, slot)
+    }
+}
+
+#[sealed]
+impl IntoWren for Closure {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        self.put(wren, slot)
+    }
+}
+
+#[sealed]
+impl IntoWren for OwnedValue {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        match self {
+            OwnedValue::Null => ().put_value(wren, slot),
+            OwnedValue::Bool(value) => value.put_value(wren, slot),
+            OwnedValue::Num(value) => value.put_value(wren, slot),
+            OwnedValue::String(value) => value.as_slice().put_value(wren, slot),
+            OwnedValue::List(list) => {
+                unsafe { wren.ensure_slots(slot + 2) };
+
+                unsafe { wren.set_slot_new_list(slot) };
+
+                for element in list {
+                    element.put_value(wren, slot + 1)?;
+
+                    unsafe { wren.insert_in_list(slot, -1, slot + 1) };
+                }
+
+                Ok(())
+            }
+            OwnedValue::Handle(handle) => handle.put_value(wren, slot),
+        }
+    }
+}
+
+/// A reference to a Wren object which keeps it from being garbage collected.
+///
+/// Like a [`CallHandle`], a handle keeps the underlying virtual machine
+/// allocated after the [`Wren`] it came from is dropped, but can no longer be
+/// used.
+///
+/// [`CallHandle`]: crate::CallHandle
+/// [`Wren`]: crate::Wren
+///
+/// Handles may be sent to and dropped on any thread. The underlying Wren handle
+/// is released the next time its [`Wren`] is used.
 pub struct Handle(WrenPtr, HandlePtr);
 
+// Safety: A handle can only be used through its `Wren`, and dropping it only
+// touches the synchronised parts of the header.
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// The virtual machine which this handle belongs to.
+    pub(crate) fn vm(&self) -> &WrenPtr {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle").finish_non_exhaustive()
+    }
+}
+
 #[sealed]
 impl IntoWren for Handle {
     fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
         assert_eq!(self.0, *wren);
 
+        unsafe { wren.ensure_slots(slot + 1) };
         unsafe { wren.set_slot_handle(slot, self.1) };
 
         Ok(())
@@ -49,9 +238,55 @@ impl FromWren<'_> for Handle {
 
 impl Drop for Handle {
     fn drop(&mut self) {
-        unsafe { self.0.release_handle(self.1) };
+        // Safety: The virtual machine is kept alive by the reference this
+        // handle owns.
+        unsafe { WrenHeader::defer_release(self.0, self.1) };
+
+        unsafe { WrenHeader::release(self.0) };
+    }
+}
+
+/// The iterator value returned by a Wren sequence's `iterate(_)` method,
+/// which is `None` once the sequence is exhausted.
+pub(crate) struct IteratorState(pub Option<Handle>);
+
+#[sealed]
+impl FromWren<'_> for IteratorState {
+    fn get_value(wren: &WrenPtr, slot: usize) -> Result<Self, Error> {
+        // Only `null` and `false` end iteration, so the type of the value is
+        // checked directly instead of reading it as a `Value`.
+        let done = match unsafe { wren.get_slot_type(slot) } {
+            WrenType::Null => true,
+            WrenType::Bool => !unsafe { wren.get_slot_bool(slot) },
+            _ => false,
+        };
+
+        if done {
+            Ok(IteratorState(None))
+        } else {
+            Ok(IteratorState(Some(Handle::get_value(wren, slot)?)))
+        }
+    }
+}
+
+#[sealed]
+impl FromWren<'_> for Fiber {
+    fn get_value(wren: &WrenPtr, slot: usize) -> Result<Self, Error> {
+        Ok(Fiber::from_handle(Handle::get_value(wren, slot)?))
+    }
+}
+
+#[sealed]
+impl IntoWren for Fiber {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        self.handle().put_value(wren, slot)
+    }
+}
 
-        unsafe { WrenHeader::release(self.0.get_user_data()) };
+#[sealed]
+impl IntoWren for WrenFn {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        self.handle().put_value(wren, slot)
     }
 }
 
@@ -60,6 +295,17 @@ pub trait FromWren<'s>: Sized {
     fn get_value(wren: &'s WrenPtr, slot: usize) -> Result<Self, Error>;
 }
 
+/// Creates the error for a value in `slot` which is not of an `expected` type.
+fn mismatch(wren: &WrenPtr, slot: usize, expected: &'static [WrenType]) -> Error {
+    let found = if slot < wren.get_slot_count() {
+        unsafe { wren.get_slot_type(slot) }
+    } else {
+        WrenType::Null
+    };
+
+    Error::MismatchedValue(MismatchedValueError::new(expected, found))
+}
+
 #[sealed]
 impl<'s> FromWren<'s> for Value<'s> {
     fn get_value(wren: &'s WrenPtr, slot: usize) -> Result<Self, Error> {
@@ -84,10 +330,18 @@ impl<'s> FromWren<'s> for Value<'s> {
 
                 Ok(Value::String(unsafe { &*value }))
             }
-            WrenType::List => todo!(),
-            WrenType::Map => todo!(),
-            WrenType::Unknown => todo!(),
-            WrenType::Foreign => todo!(),
+            found @ (WrenType::List | WrenType::Map | WrenType::Unknown | WrenType::Foreign) => {
+                let expected = &[
+                    WrenType::Null,
+                    WrenType::Bool,
+                    WrenType::Num,
+                    WrenType::String,
+                ];
+
+                Err(Error::MismatchedValue(MismatchedValueError::new(
+                    expected, found,
+                )))
+            }
         }
     }
 }
@@ -96,7 +350,7 @@ impl<'s> FromWren<'s> for Value<'s> {
 impl FromWren<'_> for () {
     fn get_value(wren: &WrenPtr, slot: usize) -> Result<Self, Error> {
         let Value::Null = Value::get_value(wren, slot)? else {
-            todo!()
+            return Err(mismatch(wren, slot, &[WrenType::Null]));
         };
 
         Ok(())
@@ -107,7 +361,7 @@ impl FromWren<'_> for () {
 impl FromWren<'_> for bool {
     fn get_value(wren: &WrenPtr, slot: usize) -> Result<Self, Error> {
         let Value::Bool(value) = Value::get_value(wren, slot)? else {
-            todo!()
+            return Err(mismatch(wren, slot, &[WrenType::Bool]));
         };
 
         Ok(value)
@@ -118,7 +372,7 @@ impl FromWren<'_> for bool {
 impl FromWren<'_> for f64 {
     fn get_value(wren: &'_ WrenPtr, slot: usize) -> Result<Self, Error> {
         let Value::Num(value) = Value::get_value(wren, slot)? else {
-            todo!();
+            return Err(mismatch(wren, slot, &[WrenType::Num]));
         };
 
         Ok(value)
@@ -129,7 +383,7 @@ impl FromWren<'_> for f64 {
 impl<'s> FromWren<'s> for &'s [u8] {
     fn get_value(wren: &'s WrenPtr, slot: usize) -> Result<Self, Error> {
         let Value::String(value) = Value::get_value(wren, slot)? else {
-            todo!();
+            return Err(mismatch(wren, slot, &[WrenType::String]));
         };
 
         Ok(value)
@@ -219,6 +473,23 @@ impl IntoWren for str {
     }
 }
 
+#[sealed]
+impl IntoWren for String {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        self.as_str().put_value(wren, slot)
+    }
+}
+
+#[sealed]
+impl<T: IntoWren> IntoWren for Option<T> {
+    fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
+        match self {
+            Some(value) => value.put_value(wren, slot),
+            None => ().put_value(wren, slot),
+        }
+    }
+}
+
 #[sealed]
 impl<T: IntoWren + ?Sized> IntoWren for &T {
     fn put_value(&self, wren: &WrenPtr, slot: usize) -> Result<(), Error> {
@@ -228,12 +499,16 @@ impl<T: IntoWren + ?Sized> IntoWren for &T {
 
 /// A tuple of values which can be passed into a Wren function call.
 ///
-/// This trait is implemented for tuples of length one to sixteen inclusive.
+/// This trait is implemented for tuples of length one to sixteen inclusive,
+/// and for slices and vectors of values.
 /// Passing no arguments is not supported, as all Wren methods require a
 /// reciever class.
 #[sealed]
 pub trait WrenArguments {
     fn prepare(&self, wren: &WrenPtr) -> Result<(), Error>;
+
+    /// The number of arguments.
+    fn count(&self) -> usize;
 }
 
 #[sealed]
@@ -241,6 +516,47 @@ impl WrenArguments for () {
     fn prepare(&self, _wren: &WrenPtr) -> Result<(), Error> {
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        0
+    }
+}
+
+#[sealed]
+impl<T: IntoWren> WrenArguments for [T] {
+    fn prepare(&self, wren: &WrenPtr) -> Result<(), Error> {
+        for (index, value) in self.iter().enumerate() {
+            value.put_value(wren, index + 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+#[sealed]
+impl<T: IntoWren> WrenArguments for Vec<T> {
+    fn prepare(&self, wren: &WrenPtr) -> Result<(), Error> {
+        self.as_slice().prepare(wren)
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+#[sealed]
+impl<A: WrenArguments + ?Sized> WrenArguments for &A {
+    fn prepare(&self, wren: &WrenPtr) -> Result<(), Error> {
+        (*self).prepare(wren)
+    }
+
+    fn count(&self) -> usize {
+        (*self).count()
+    }
 }
 
 #[sealed]
@@ -250,6 +566,10 @@ impl<A: IntoWren> WrenArguments for (A,) {
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        1
+    }
 }
 
 #[sealed]
@@ -260,6 +580,10 @@ impl<A: IntoWren, B: IntoWren> WrenArguments for (A, B) {
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        2
+    }
 }
 
 #[sealed]
@@ -271,6 +595,10 @@ impl<A: IntoWren, B: IntoWren, C: IntoWren> WrenArguments for (A, B, C) {
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        3
+    }
 }
 
 #[sealed]
@@ -283,6 +611,10 @@ impl<A: IntoWren, B: IntoWren, C: IntoWren, D: IntoWren> WrenArguments for (A, B
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        4
+    }
 }
 
 #[sealed]
@@ -298,6 +630,10 @@ impl<A: IntoWren, B: IntoWren, C: IntoWren, D: IntoWren, E: IntoWren> WrenArgume
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        5
+    }
 }
 
 #[sealed]
@@ -314,6 +650,10 @@ impl<A: IntoWren, B: IntoWren, C: IntoWren, D: IntoWren, E: IntoWren, F: IntoWre
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        6
+    }
 }
 
 #[sealed]
@@ -331,6 +671,10 @@ impl<A: IntoWren, B: IntoWren, C: IntoWren, D: IntoWren, E: IntoWren, F: IntoWre
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        7
+    }
 }
 
 #[sealed]
@@ -357,4 +701,8 @@ impl<
 
         Ok(())
     }
+
+    fn count(&self) -> usize {
+        8
+    }
 }
@@ -11,27 +11,53 @@
 //! running code. To ensure this, code may only be run if a mutable reference
 //! to the `Wren` is taken.
 //!
-//! The slot array may be modified at any time, even under an immutable
-//! reference. Therefore, code may not assume that a slot's value will not
-//! change.
+//! Values such as strings may be borrowed directly out of the slot array, and
+//! so the slot array may only be modified under a mutable reference. Every
+//! method which returns a value borrowed from a slot ties it to that mutable
+//! reference, so the slot cannot be overwritten, and its value cannot be
+//! garbage collected, while the borrow is alive.
+//!
+//! ```compile_fail
+//! # let mut wren = wrenlet::Wren::new();
+//! # wren.interpret("main", "var name = \"wren\"").unwrap();
+//! let name = wren.get_variable::<&str>("main", "name").unwrap();
+//!
+//! // The `Wren` is still borrowed by `name`.
+//! let handle = wren.make_call_handle("count").unwrap();
+//!
+//! assert_eq!(name, "wren");
+//! ```
 //!
 //! [`WrenVM`]: sys::WrenVM
 //! [`wrenFreeVM`]: sys::wrenFreeVM
 
 use std::{
     alloc::{Layout, handle_alloc_error},
-    ffi::CString,
-    io::Stdout,
+    ffi::{CStr, CString},
+    io::{Read, Stdout},
     marker::PhantomData,
     mem::MaybeUninit,
+    ops::Deref,
+    path::Path,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering, fence},
+    },
+    time::{Duration, Instant},
 };
 
 use crate::{
-    Builder,
-    error::Error,
-    module::Empty,
+    Builder, Diagnostic, SequenceIter, WrenFn,
+    allocator::{self, Heap, MemoryStats},
+    bridge,
+    error::{CompileError, Error, Panic, RuntimeError},
+    foreigns::{self, ForeignClass, LiveObjects},
+    interrupt::{InterruptHandle, StepBudget},
+    module::{Empty, ModuleLoader},
     raw::{HandlePtr, InterpretError, WrenPtr},
-    value::{FromWren, IntoWren, WrenArguments},
+    scheduler::Timers,
+    tasks::{AsyncMethod, Executor, Tasks},
+    value::{FromWren, Handle, IntoWren, OwnedValue, WrenArguments},
 };
 
 /// An instance of a Wren virtual machine with associated user data.
@@ -62,6 +88,86 @@ impl Wren<()> {
     pub fn builder() -> Builder<(), Empty, Stdout> {
         Builder::new()
     }
+
+    /// Compiles `source` as the module `module`, without running it,
+    /// returning every compile error found.
+    ///
+    /// The source is compiled by a separate virtual machine, which is freed
+    /// before this returns, and so it cannot refer to variables defined by
+    /// other code. Modules it imports are not loaded, as imports only happen
+    /// when a module is run. Compile errors are returned rather than printed.
+    /// Anything else which stops the module from being compiled, such as a NUL
+    /// byte in its name, is reported as an error on the first line.
+    ///
+    /// ```
+    /// # use wrenlet::Wren;
+    /// assert!(Wren::check("main", "System.print(\"never printed\")").is_ok());
+    ///
+    /// let errors = Wren::check("main", "var a = 1\nvar b = )").unwrap_err();
+    /// assert_eq!(errors[0].line(), 2);
+    /// ```
+    pub fn check(module: &str, source: &str) -> Result<(), Vec<CompileError>> {
+        let error = |line, message: &str| {
+            vec![CompileError::new(
+                module.to_owned(),
+                line,
+                message.to_owned(),
+            )]
+        };
+
+        if module.contains('\0') {
+            return Err(error(1, "Module name contains a NUL byte."));
+        }
+
+        if let Some(index) = source.find('\0') {
+            let line = source[..index].matches('\n').count() + 1;
+
+            return Err(error(line, "Source contains a NUL byte."));
+        }
+
+        let mut wren = Wren::new();
+
+        // Wren only skips a shebang on the first line, so the suspend is
+        // placed after it. The lines of the rest of the source are moved down
+        // by one either way.
+        let (shebang, source) = if source.starts_with("#!/") {
+            source.split_once('\n').unwrap_or((source, ""))
+        } else {
+            ("", source)
+        };
+
+        // The fiber running the module is suspended before any of its code
+        // runs, but only once all of it has been compiled.
+        let source = if shebang.is_empty() {
+            format!("Fiber.suspend()\n{source}")
+        } else {
+            format!("{shebang}\nFiber.suspend()\n{source}")
+        };
+
+        match wren.interpret(module, &source) {
+            Ok(()) => Ok(()),
+            Err(Error::Compile(errors)) => {
+                Err(errors.into_iter().map(|error| error.offset(1)).collect())
+            }
+            Err(Error::OutOfMemory) => Err(error(1, "Out of memory.")),
+            Err(Error::Panic(panic)) => Err(error(1, panic.message())),
+            // None of the code runs, so nothing else should stop it from
+            // being compiled.
+            Err(_) => Err(error(1, "The module could not be compiled.")),
+        }
+    }
+}
+
+/// Returns [`Error::InteriorNul`] if any of the module or variable `names`
+/// contains a NUL byte, which Wren cannot represent.
+///
+/// Without this, such a name would be reported as missing.
+fn check_names(names: &[&str]) -> Result<(), Error> {
+    if names.iter().any(|name| name.contains('\0')) {
+        return Err(Error::InteriorNul);
+    }
+
+    Ok(())
 }
 
 impl<U, M, W> Wren<U, M, W> {
@@ -70,36 +176,98 @@ impl<U, M, W> Wren<U, M, W> {
     }
 
     /// Interprets the given `source` as Wren code in the context of the given `module`.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` or `source` contains a NUL
+    /// byte, and [`Error::Compile`] or [`Error::Runtime`] if the code fails.
     pub fn interpret(&mut self, module: &str, source: &str) -> Result<(), Error> {
-        let module = CString::new(module).unwrap();
-        let source = CString::new(source).unwrap();
+        self.interpret_bytes(module, source)
+    }
 
-        let result = unsafe { self.0.interpret(&module, &source) };
+    /// Interprets `source` like [`Wren::interpret`], without requiring it to
+    /// be valid UTF-8.
+    pub fn interpret_bytes(&mut self, module: &str, source: impl AsRef<[u8]>) -> Result<(), Error> {
+        let module = CString::new(module)?;
+        let source = CString::new(source.as_ref())?;
 
-        match result {
-            Ok(()) => Ok(()),
-            Err(InterpretError::Compile) => Err(Error::Compile),
-            Err(InterpretError::Runtime) => Err(Error::Runtime),
-        }
+        // Safety: The module and source are valid strings.
+        self.run(|vm| unsafe { vm.interpret(&module, &source) })
     }
 
-    /// Creates a compiled call handle which can be used to invoke a method on some object.
-    pub fn make_call_handle(&self, signature: &str) -> CallHandle {
-        let signature = CString::new(signature).unwrap();
+    /// Interprets a snippet of code generated by this crate.
+    ///
+    /// Errors are not printed, even if printing was enabled with
+    /// [`Builder::print_errors`], as their lines would not match the user's
+    /// code.
+    fn interpret_snippet(&mut self, module: &str, source: &str) -> Result<(), Error> {
+        // Safety: No code is running.
+        let print = std::mem::replace(unsafe { &mut (*self.header_ptr()).print_errors }, false);
 
-        let call_handle = self.0.make_call_handle(&signature);
+        // A snippet which imports the bridge does so before running any other
+        // code.
+        unsafe { (*self.header_ptr()).bridge_import = true };
 
-        unsafe { WrenHeader::claim(self.header_ptr()) };
+        let result = self.interpret(module, source);
 
-        CallHandle(self.0, call_handle)
+        unsafe { (*self.header_ptr()).bridge_import = false };
+        unsafe { (*self.header_ptr()).print_errors = print };
+
+        result
+    }
+
+    /// Reads all of `reader`, and interprets it like [`Wren::interpret`].
+    ///
+    /// # Errors
+    /// Returns [`Error::Io`] if reading fails, and otherwise the same errors
+    /// as [`Wren::interpret`].
+    pub fn interpret_reader(&mut self, module: &str, mut reader: impl Read) -> Result<(), Error> {
+        let mut source = Vec::new();
+        reader.read_to_end(&mut source)?;
+
+        self.interpret_bytes(module, source)
+    }
+
+    /// Reads the file at `path`, and interprets it like [`Wren::interpret`].
+    ///
+    /// The file is interpreted as the module named by its path, without a
+    /// `.wren` extension, as the Wren CLI does. For example, `scripts/main.wren`
+    /// is interpreted as the module `scripts/main`.
+    ///
+    /// # Errors
+    /// Returns [`Error::Io`] if the file cannot be read, and otherwise the same
+    /// errors as [`Wren::interpret`].
+    pub fn interpret_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        let source = std::fs::read(path)?;
+
+        let module = match path.extension() {
+            Some(extension) if extension == "wren" => path.with_extension(""),
+            _ => path.to_owned(),
+        };
+
+        self.interpret_bytes(&module.to_string_lossy(), source)
+    }
+
+    /// Creates a compiled call handle which can be used to invoke a method on some object.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `signature` contains a NUL byte.
+    pub fn make_call_handle(&self, signature: &str) -> Result<CallHandle, Error> {
+        if signature.contains('\0') {
+            return Err(Error::InteriorNul);
+        }
+
+        Ok(CallHandle::new(&self.0, signature))
     }
 
     /// Calls a method on `reciever` with the given arguments.
     ///
-    /// A [`CallHandle`] can be created with [`Wren::make_call_handle`].
+    /// A [`CallHandle`] can be created with [`Wren::make_call_handle`], and
+    /// used for any number of calls.
     pub fn call<'a, T>(
         &'a mut self,
-        handle: CallHandle,
+        handle: &CallHandle,
         reciever: impl IntoWren,
         args: impl WrenArguments,
     ) -> Result<T, Error>
@@ -112,20 +280,124 @@ impl<U, M, W> Wren<U, M, W> {
 
         args.prepare(&self.0)?;
 
-        match unsafe { self.0.call(handle.1) } {
-            Ok(()) => T::get_value(&self.0, 0),
-            Err(crate::raw::InterpretError::Runtime) => todo!(),
-            Err(crate::raw::InterpretError::Compile) => todo!(),
+        // Safety: The handle belongs to this virtual machine, and the reciever
+        // and arguments were placed in the slots above.
+        self.run(|vm| unsafe { vm.call(handle.1) })?;
+
+        T::get_value(&self.0, 0)
+    }
+
+    /// Runs code in the virtual machine with `f`, converting its result into
+    /// an [`Error`].
+    ///
+    /// No code is run if the virtual machine is over its memory limit, and
+    /// exceeding the limit while running is reported as an error regardless
+    /// of the result of `f`.
+    fn run(&mut self, f: impl FnOnce(&WrenPtr) -> Result<(), InterpretError>) -> Result<(), Error> {
+        if unsafe { self.header() }.heap.is_over_limit() {
+            self.collect_garbage();
+
+            if unsafe { self.header() }.heap.is_over_limit() {
+                return Err(Error::OutOfMemory);
+            }
         }
+
+        self.heap_mut().clear_exhausted();
+
+        self.release_pending();
+
+        // Interrupts, budgets and errors only apply to the code about to be
+        // run.
+        unsafe { self.header() }.interrupt.clear();
+        unsafe { (*self.header_ptr()).step_budget.reset() };
+        self.take_error();
+        self.take_compile_errors();
+        self.take_runtime_error();
+        unsafe { (*self.header_ptr()).panic = None };
+
+        let result = f(&self.0);
+
+        // Memory allocated since the last step of the fiber has not been
+        // checked against the limit yet.
+        if unsafe { self.header() }.heap.is_exhausted()
+            || unsafe { allocator::is_out_of_memory(&self.0) }
+        {
+            self.collect_garbage();
+
+            unsafe { (*self.header_ptr()).faulted = true };
+
+            return Err(Error::OutOfMemory);
+        }
+
+        unsafe { self.header() }.interrupt.clear();
+
+        let result = match self.take_error() {
+            Some(error) => Err(error),
+            None => result.map_err(|error| match error {
+                InterpretError::Compile => Error::Compile(self.take_compile_errors()),
+                InterpretError::Runtime => Error::Runtime(self.take_runtime_error()),
+            }),
+        };
+
+        if let Err(Error::Runtime(_) | Error::Interrupted) = result {
+            // Safety: The flag is only accessed by the thread which owns this
+            // `Wren`.
+            unsafe { (*self.header_ptr()).faulted = true };
+        }
+
+        result
+    }
+
+    /// Returns `true` if code run by this virtual machine has ever been
+    /// aborted, by a runtime error, an interrupt or the memory limit.
+    pub(crate) fn has_faulted(&self) -> bool {
+        unsafe { self.header() }.faulted
+    }
+
+    /// Returns `true` if a fiber is waiting to be resumed by a task or a
+    /// timer.
+    pub(crate) fn has_pending_fibers(&self) -> bool {
+        let header = unsafe { self.header() };
+
+        header.tasks.has_parked() || header.timers.next_deadline().is_some()
     }
 
     /// Looks up the top level variable in `module` called `name`.
-    pub fn get_variable<'s, T>(&'s self, module: &str, name: &str) -> Result<T, Error>
+    ///
+    /// The returned value may borrow from the virtual machine, in which case
+    /// the `Wren` remains mutably borrowed until it is dropped.
+    ///
+    /// ```compile_fail
+    /// # let mut wren = wrenlet::Wren::new();
+    /// # wren.interpret("main", "var a = \"a\"\nvar b = \"b\"").unwrap();
+    /// let a = wren.get_variable::<&str>("main", "a").unwrap();
+    /// // Loading `b` would overwrite the slot which `a` borrows from.
+    /// let b = wren.get_variable::<&str>("main", "b").unwrap();
+    ///
+    /// assert_eq!(a, "a");
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` or `name` contains a NUL
+    /// byte, [`Error::NoSuchModule`] if `module` has not been loaded, and
+    /// [`Error::NoSuchVariable`] if it does not define a variable `name`.
+    pub fn get_variable<'s, T>(&'s mut self, module: &str, name: &str) -> Result<T, Error>
     where
         T: FromWren<'s>,
     {
-        let module = CString::new(module).unwrap();
-        let name = CString::new(name).unwrap();
+        let module = CString::new(module)?;
+        let name = CString::new(name)?;
+
+        self.release_pending();
+
+        if !self.0.has_module(&module) {
+            return Err(Error::NoSuchModule);
+        }
+
+        // Safety: The module was checked to exist above.
+        if !unsafe { self.0.has_variable(&module, &name) } {
+            return Err(Error::NoSuchVariable);
+        }
 
         unsafe { self.0.ensure_slots(1) };
         unsafe { self.0.get_variable(&module, &name, 0) };
@@ -133,12 +405,634 @@ impl<U, M, W> Wren<U, M, W> {
         T::get_value(&self.0, 0)
     }
 
+    /// Sets the top level variable `name` in `module` to `value`.
+    ///
+    /// If the module does not already define a variable called `name`, it is
+    /// defined before being set.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` or `name` contains a NUL
+    /// byte, [`Error::InvalidIdentifier`] if `name` is not a valid name for a
+    /// top level variable, and [`Error::NoSuchModule`] if `module` has not
+    /// been loaded. Use [`Wren::define_module`] to create a new module.
+    pub fn set_variable(
+        &mut self,
+        module: &str,
+        name: &str,
+        value: impl IntoWren,
+    ) -> Result<(), Error> {
+        check_names(&[module, name])?;
+
+        if !bridge::is_identifier(name) {
+            return Err(Error::InvalidIdentifier);
+        }
+
+        if !self.has_module(module) {
+            return Err(Error::NoSuchModule);
+        }
+
+        if !self.has_variable(module, name) {
+            self.interpret_snippet(module, &format!("var {name} = null"))?;
+        }
+
+        self.stash(value)?;
+
+        self.interpret_snippet(module, &bridge::assign_from_stash(name))
+    }
+
+    /// Evaluates `expression` in the scope of `module`, returning its value.
+    ///
+    /// The expression can refer to any top level variable of the module, but
+    /// cannot define new ones.
+    ///
+    /// ```
+    /// # use wrenlet::Wren;
+    /// let mut wren = Wren::new();
+    ///
+    /// wren.interpret("main", "var config = { \"width\": 640 }").unwrap();
+    ///
+    /// assert_eq!(wren.eval::<f64>("main", "1 + 2").unwrap(), 3.0);
+    /// assert_eq!(wren.eval::<f64>("main", "config[\"width\"] / 2").unwrap(), 320.0);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` contains a NUL byte,
+    /// [`Error::NoSuchModule`] if `module` has not been loaded, and
+    /// [`Error::Compile`] or [`Error::Runtime`] if the expression fails. The
+    /// lines of compile errors, and of the expression in stack traces, are
+    /// counted from the start of the expression.
+    pub fn eval<'s, T>(&'s mut self, module: &str, expression: &str) -> Result<T, Error>
+    where
+        T: FromWren<'s>,
+    {
+        check_names(&[module])?;
+
+        if !self.has_module(module) {
+            return Err(Error::NoSuchModule);
+        }
+
+        let source = bridge::stash_expression(expression);
+
+        self.interpret_snippet(module, &source)
+            .map_err(|error| error.offset(module, bridge::EXPRESSION_OFFSET))?;
+
+        self.call_bridge(c"take()", ())?;
+
+        T::get_value(&self.0, 0)
+    }
+
+    /// Compiles `body` as a function taking the given `params`, in the scope
+    /// of `module`.
+    ///
+    /// The function is compiled once, and can then be called any number of
+    /// times through the returned [`WrenFn`]. As with a Wren block, a body on
+    /// a single line is an expression whose value is returned, while a body
+    /// spanning several lines is a list of statements, which must use `return`
+    /// to return a value.
+    ///
+    /// ```
+    /// # use wrenlet::Wren;
+    /// let mut wren = Wren::new();
+    ///
+    /// wren.interpret("main", "var limit = 10").unwrap();
+    ///
+    /// let rule = wren.compile_function("main", &["a", "b"], "a + b > limit").unwrap();
+    ///
+    /// assert!(rule.call::<bool, _, _, _>(&mut wren, (4.0, 7.0)).unwrap());
+    /// assert!(!rule.call::<bool, _, _, _>(&mut wren, (4.0, 5.0)).unwrap());
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` contains a NUL byte,
+    /// [`Error::InvalidIdentifier`] if a parameter is not a valid name,
+    /// [`Error::NoSuchModule`] if `module` has not been loaded, and
+    /// [`Error::Compile`] if the body is invalid. The lines of compile errors
+    /// are counted from the start of the body.
+    pub fn compile_function(
+        &mut self,
+        module: &str,
+        params: &[&str],
+        body: &str,
+    ) -> Result<WrenFn, Error> {
+        check_names(&[module])?;
+
+        if !params.iter().all(|param| bridge::is_identifier(param)) {
+            return Err(Error::InvalidIdentifier);
+        }
+
+        let expression = bridge::function(params, body);
+
+        let function = self
+            .eval::<Handle>(module, &expression)
+            .map_err(|error| error.offset(module, bridge::FUNCTION_OFFSET))?;
+
+        // The body is compiled as part of an expression, and so its lines are
+        // offset from the start of the expression too.
+        let offset = bridge::EXPRESSION_OFFSET + bridge::FUNCTION_OFFSET;
+
+        Ok(WrenFn::new(self, function)?.with_offset(module, offset))
+    }
+
+    /// Defines each of the given `variables` as top level variables in
+    /// `module`, creating the module if it has not been loaded.
+    ///
+    /// Variables which already exist in the module are overwritten.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` contains a NUL byte, and
+    /// otherwise the same errors as [`Wren::set_variable`] for each variable.
+    pub fn define_module<K, V>(
+        &mut self,
+        module: &str,
+        variables: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<str>,
+        V: IntoWren,
+    {
+        check_names(&[module])?;
+
+        if !self.has_module(module) {
+            self.interpret(module, "")?;
+        }
+
+        for (name, value) in variables {
+            self.set_variable(module, name.as_ref(), value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `source` in the context of `module` using `Meta.eval`.
+    ///
+    /// Unlike [`Wren::interpret`], the source is compiled by the running
+    /// script, so it can only define variables local to itself.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` contains a NUL byte, and
+    /// [`Error::NoSuchModule`] if either `module` has not been loaded, or the
+    /// `meta` module was disabled for this virtual machine.
+    #[cfg(feature = "meta")]
+    pub fn meta_eval(&mut self, module: &str, source: &str) -> Result<(), Error> {
+        check_names(&[module])?;
+
+        if !self.has_module(module) || !unsafe { self.header() }.meta {
+            return Err(Error::NoSuchModule);
+        }
+
+        self.stash(source)?;
+
+        let body = format!(
+            "  import \"meta\" for Meta\n  Meta.eval({}.take())",
+            bridge::ALIAS
+        );
+
+        self.interpret_snippet(module, &bridge::block(&body))
+    }
+
+    /// Evaluates `expression` in the context of `module` using
+    /// `Meta.compileExpression`, and returns its result.
+    ///
+    /// # Errors
+    /// Returns [`Error::InteriorNul`] if `module` contains a NUL byte, and
+    /// [`Error::NoSuchModule`] if either `module` has not been loaded, or the
+    /// `meta` module was disabled for this virtual machine. If the
+    /// expression does not compile, the script is aborted and
+    /// [`Error::Runtime`] is returned.
+    #[cfg(feature = "meta")]
+    pub fn meta_expression<'s, T>(&'s mut self, module: &str, expression: &str) -> Result<T, Error>
+    where
+        T: FromWren<'s>,
+    {
+        check_names(&[module])?;
+
+        if !self.has_module(module) || !unsafe { self.header() }.meta {
+            return Err(Error::NoSuchModule);
+        }
+
+        self.stash(expression)?;
+
+        let body = format!(
+            "  import \"meta\" for Meta\n  \
+            var fn = Meta.compileExpression({0}.take())\n  \
+            if (fn == null) Fiber.abort(\"Could not compile expression.\")\n  \
+            {0}.stash = fn.call()",
+            bridge::ALIAS
+        );
+
+        self.interpret_snippet(module, &bridge::block(&body))?;
+
+        self.unstash()
+    }
+
+    /// Places `value` in the bridge module, to be taken by the next snippet
+    /// which calls `Bridge.take()`.
+    fn stash(&mut self, value: impl IntoWren) -> Result<(), Error> {
+        self.call_bridge(c"stash=(_)", (value,))
+    }
+
+    /// Takes the value most recently placed in the bridge module by a snippet
+    /// which assigns to `Bridge.stash`.
+    fn unstash<'s, T>(&'s mut self) -> Result<T, Error>
+    where
+        T: FromWren<'s>,
+    {
+        self.call_bridge(c"take()", ())?;
+
+        T::get_value(&self.0, 0)
+    }
+
+    /// Iterates over the elements of the Wren sequence `sequence`, converting
+    /// each one to `T`.
+    ///
+    /// See [`SequenceIter`].
+    ///
+    /// ```
+    /// # use wrenlet::{Wren, value::Handle};
+    /// let mut wren = Wren::new();
+    /// wren.interpret("main", "var range = 1..3").unwrap();
+    ///
+    /// let range: Handle = wren.get_variable("main", "range").unwrap();
+    /// let numbers: Result<Vec<f64>, _> = wren.iter(&range).collect();
+    ///
+    /// assert_eq!(numbers.unwrap(), [1.0, 2.0, 3.0]);
+    /// ```
+    pub fn iter<'w, T>(&'w mut self, sequence: &'w Handle) -> SequenceIter<'w, T, U, M, W>
+    where
+        T: for<'a> FromWren<'a>,
+    {
+        SequenceIter::new(self, sequence)
+    }
+
+    /// Reads the value held by `value` as a `T`.
+    pub(crate) fn convert<'s, T>(&'s mut self, value: &Handle) -> Result<T, Error>
+    where
+        T: FromWren<'s>,
+    {
+        value.put_value(&self.0, 0)?;
+
+        T::get_value(&self.0, 0)
+    }
+
+    /// Creates a new fiber which runs `function`.
+    ///
+    /// Aborts with a runtime error if `function` is not a function.
+    pub(crate) fn new_fiber(&mut self, function: &Handle) -> Result<Handle, Error> {
+        self.call_bridge(c"fiber(_)", (function,))?;
+
+        Handle::get_value(&self.0, 0)
+    }
+
+    /// Returns the arity of `value` if it is a function, or `None` otherwise.
+    pub(crate) fn function_arity(&mut self, value: &Handle) -> Result<Option<usize>, Error> {
+        self.call_bridge(c"arity(_)", (value,))?;
+
+        match OwnedValue::get_value(&self.0, 0)? {
+            OwnedValue::Num(arity) => Ok(Some(arity as usize)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets the executor which runs the futures created by async methods.
+    pub fn set_executor(&mut self, executor: impl Executor + 'static) {
+        // Safety: The executor is only accessed by the thread which owns this
+        // `Wren`.
+        unsafe { (*self.header_ptr()).executor = Some(Arc::new(executor)) };
+    }
+
+    /// Registers `function` as the implementation of the static foreign
+    /// method `signature` of `class` in `module`.
+    ///
+    /// The method must be declared in Wren with `foreign static`, and is bound
+    /// when that declaration is interpreted, so it must be registered
+    /// beforehand. Calling the method passes its arguments to `function`, and
+    /// returns a `Task` object to Wren. Awaiting the task with `task.await`
+    /// parks the calling fiber, and suspends the virtual machine.
+    ///
+    /// Once the future completes, the fiber can be resumed with its value by
+    /// [`Wren::resume_tasks`] or [`Wren::wait_for_tasks`]. If it fails, the
+    /// fiber is aborted with its error message instead.
+    ///
+    /// ```
+    /// # use wrenlet::{BoxFuture, Wren, value::OwnedValue};
+    /// let mut wren = Wren::new();
+    ///
+    /// // Runs each future to completion on the current thread.
+    /// wren.set_executor(|mut future: BoxFuture<()>| {
+    ///     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    ///     while future.as_mut().poll(&mut cx).is_pending() {}
+    /// });
+    ///
+    /// wren.register_async_method("main", "Db", "get(_)", |args: Vec<OwnedValue>| async move {
+    ///     match &args[..] {
+    ///         [OwnedValue::String(key)] => Ok(format!("value of {}", String::from_utf8_lossy(key))),
+    ///         _ => Err("expected a key"),
+    ///     }
+    /// });
+    ///
+    /// let source = r#"
+    ///     class Db {
+    ///         foreign static get(key)
+    ///     }
+    ///     var value = Db.get("a").await
+    /// "#;
+    /// wren.interpret("main", source).unwrap();
+    ///
+    /// // The main fiber is parked until its task is resumed.
+    /// wren.wait_for_tasks().unwrap();
+    ///
+    /// assert_eq!(wren.get_variable::<String>("main", "value").unwrap(), "value of a");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if more than 64 async methods are registered.
+    pub fn register_async_method<F, Fut, T, E>(
+        &mut self,
+        module: &str,
+        class: &str,
+        signature: &str,
+        function: F,
+    ) where
+        F: Fn(Vec<OwnedValue>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+        T: Into<OwnedValue>,
+        E: ToString,
+    {
+        // Safety: The methods are only accessed by the thread which owns this
+        // `Wren`.
+        let methods = unsafe { &mut (*self.header_ptr()).async_methods };
+
+        assert!(
+            methods.len() < foreigns::MAX_ASYNC_METHODS,
+            "at most {} async methods can be registered",
+            foreigns::MAX_ASYNC_METHODS
+        );
+
+        methods.push(AsyncMethod {
+            module: module.to_owned(),
+            class: class.to_owned(),
+            signature: signature.to_owned(),
+            function: Arc::new(move |arguments| {
+                let future = function(arguments);
+
+                Box::pin(async move {
+                    future
+                        .await
+                        .map(Into::into)
+                        .map_err(|error| error.to_string())
+                })
+            }),
+        });
+    }
+
+    /// Resumes every fiber whose awaited task has completed, returning the
+    /// number of fibers resumed.
+    ///
+    /// This does not block, and fibers whose tasks are still running remain
+    /// parked.
+    pub fn resume_tasks(&mut self) -> Result<usize, Error> {
+        let mut resumed = 0;
+
+        while let Some((fiber, result)) = unsafe { self.header() }.tasks.take_ready() {
+            match result {
+                Ok(value) => self.call_bridge(c"resume(_,_)", (fiber, value))?,
+                Err(error) => self.call_bridge(c"fail(_,_)", (fiber, error.as_str()))?,
+            }
+
+            resumed += 1;
+        }
+
+        Ok(resumed)
+    }
+
+    /// Resumes fibers as their awaited tasks complete, blocking until no
+    /// fibers are left waiting.
+    pub fn wait_for_tasks(&mut self) -> Result<(), Error> {
+        loop {
+            self.resume_tasks()?;
+
+            let tasks = unsafe { self.header() }.tasks.clone();
+
+            if !tasks.has_waiting() {
+                return Ok(());
+            }
+
+            tasks.wait();
+        }
+    }
+
+    /// Advances the scheduler's clock to `now`, then resumes every fiber whose
+    /// timer has expired, and every fiber whose awaited task has completed.
+    ///
+    /// The clock starts at zero when the virtual machine is built, and is only
+    /// moved by the host, so a game loop can call this once per frame with the
+    /// time elapsed since it started. Calling `Timer.sleep(ms)` from the `timer`
+    /// module suspends the calling fiber until the clock has moved on by `ms`
+    /// milliseconds, and `Scheduler.add {}` from the `scheduler` module runs
+    /// a function in a new fiber on the next tick.
+    ///
+    /// Fibers whose timers are scheduled during this tick are only resumed by
+    /// a later tick, even if their timers have already expired. Fibers are
+    /// resumed in the order of their deadlines, and fibers with the same
+    /// deadline in the order they were scheduled.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use wrenlet::Wren;
+    /// let mut wren = Wren::new();
+    ///
+    /// let source = r#"
+    ///     import "timer" for Timer
+    ///     var frames = 0
+    ///     while (true) {
+    ///         Timer.sleep(16)
+    ///         frames = frames + 1
+    ///     }
+    /// "#;
+    /// wren.interpret("main", source).unwrap();
+    ///
+    /// wren.tick(Duration::from_millis(20)).unwrap();
+    /// wren.tick(Duration::from_millis(40)).unwrap();
+    ///
+    /// assert_eq!(wren.get_variable::<f64>("main", "frames").unwrap(), 2.0);
+    /// ```
+    ///
+    /// # Errors
+    /// If a resumed fiber is aborted, the error is returned, and the fibers
+    /// which have not yet been resumed are left for the next tick.
+    pub fn tick(&mut self, now: Duration) -> Result<(), Error> {
+        // Safety: The timers are only accessed by the thread which owns this
+        // `Wren`, and no reference to them is held while code runs.
+        let before = unsafe {
+            (*self.header_ptr()).timers.advance(now);
+            (*self.header_ptr()).timers.next_id()
+        };
+
+        while let Some(fiber) = unsafe { (*self.header_ptr()).timers.pop_expired(before) } {
+            self.call_bridge(c"resume(_,_)", (fiber, OwnedValue::Null))?;
+        }
+
+        self.resume_tasks()?;
+
+        Ok(())
+    }
+
+    /// Resumes fibers as their timers expire and their tasks complete,
+    /// blocking until no fibers are left waiting.
+    ///
+    /// Unlike [`Wren::tick`], the scheduler's clock follows real time while
+    /// this runs.
+    pub fn run_until_idle(&mut self) -> Result<(), Error> {
+        let start = Instant::now();
+        let origin = unsafe { self.header() }.timers.now();
+
+        loop {
+            self.tick(origin + start.elapsed())?;
+
+            let tasks = unsafe { self.header() }.tasks.clone();
+            let deadline = unsafe { self.header() }.timers.next_deadline();
+
+            match deadline {
+                None if !tasks.has_waiting() => return Ok(()),
+                None => tasks.wait(),
+                Some(deadline) => {
+                    let timeout = deadline.saturating_sub(origin + start.elapsed());
+
+                    if tasks.has_waiting() {
+                        tasks.wait_timeout(timeout);
+                    } else {
+                        std::thread::sleep(timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Loads the bridge module, if it has not been loaded already.
+    pub(crate) fn load_bridge(&mut self) -> Result<(), Error> {
+        if self.has_module(bridge::MODULE) {
+            return Ok(());
+        }
+
+        self.interpret_snippet(bridge::MODULE, bridge::SOURCE)
+    }
+
+    /// Calls the static method `signature` on the bridge class with the given
+    /// arguments, loading the bridge module if necessary.
+    ///
+    /// The return value of the method is left in slot zero.
+    fn call_bridge(
+        &mut self,
+        signature: &CStr,
+        arguments: impl WrenArguments,
+    ) -> Result<(), Error> {
+        self.load_bridge()?;
+
+        unsafe { self.0.ensure_slots(2) };
+        // Safety: The bridge module was loaded above, and defines its class.
+        unsafe { self.0.get_variable(bridge::MODULE_C, bridge::CLASS_C, 0) };
+
+        arguments.prepare(&self.0)?;
+
+        let handle = self.0.make_call_handle(signature);
+
+        // Safety: The reciever and arguments were placed in the slots above.
+        let result = self.run(|vm| unsafe { vm.call(handle) });
+
+        unsafe { self.0.release_handle(handle) };
+
+        result
+    }
+
+    /// Immediately runs the garbage collector, freeing any objects which are
+    /// no longer reachable.
+    pub fn collect_garbage(&mut self) {
+        // The values of dropped handles can then be collected.
+        self.release_pending();
+
+        // Safety: No code is running, as this requires a mutable reference.
+        unsafe { self.0.collect_garbage() };
+    }
+
+    /// Gets statistics about the memory currently used by this virtual
+    /// machine.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.release_pending();
+
+        let header = unsafe { self.header() };
+
+        MemoryStats {
+            allocated: header.heap.allocated(),
+            peak_allocated: header.heap.peak(),
+            collections: self.0.collection_count(),
+            // The reference held by this `Wren` is not a handle. Handles may
+            // be dropped on other threads while the counts are read.
+            handles: header
+                .ref_count
+                .load(Ordering::Relaxed)
+                .saturating_sub(1)
+                .saturating_sub(header.internal_handles.load(Ordering::Relaxed))
+                .saturating_sub(header.tasks.parked())
+                .saturating_sub(header.timers.len()),
+            foreign_allocated: header.heap.foreign(),
+        }
+    }
+
+    /// Returns `true` if a module called `module` has been loaded.
+    pub fn has_module(&self, module: &str) -> bool {
+        // No module can have a name containing a NUL byte.
+        let Ok(module) = CString::new(module) else {
+            return false;
+        };
+
+        self.0.has_module(&module)
+    }
+
+    /// Returns `true` if `module` has been loaded and defines a top level
+    /// variable called `name`.
+    pub fn has_variable(&self, module: &str, name: &str) -> bool {
+        let (Ok(module), Ok(name)) = (CString::new(module), CString::new(name)) else {
+            return false;
+        };
+
+        // Safety: `has_variable` is only called once the module is known to exist.
+        self.0.has_module(&module) && unsafe { self.0.has_variable(&module, &name) }
+    }
+
+    /// Gets a handle which can be used to interrupt code running in this
+    /// virtual machine from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        unsafe { self.header() }.interrupt.clone()
+    }
+
+    /// Releases the handles which have been dropped since the virtual machine
+    /// last released them.
+    fn release_pending(&self) {
+        // Safety: A `Wren` is not `Sync`, so no other thread is using the
+        // virtual machine, and Wren allows handles to be released even while
+        // a foreign method is running.
+        unsafe { WrenHeader::release_pending(self.0) };
+    }
+
     fn take_error(&mut self) -> Option<Error> {
-        todo!()
+        self.error_mut().take()
+    }
+
+    fn take_compile_errors(&mut self) -> Vec<CompileError> {
+        // Safety: The errors are only accessed by the thread which owns this
+        // `Wren`.
+        std::mem::take(unsafe { &mut (*self.header_ptr()).compile_errors })
+    }
+
+    fn take_runtime_error(&mut self) -> RuntimeError {
+        // Safety: As in `take_compile_errors`.
+        std::mem::take(unsafe { &mut (*self.header_ptr()).runtime_error })
     }
 
     fn error_mut(&mut self) -> &mut Option<Error> {
-        todo!()
+        // Safety: The error is only accessed by the thread which owns this
+        // `Wren`.
+        unsafe { &mut (*self.header_ptr()).error }
     }
 
     fn data_ptr(&self) -> *mut WrenData<U, M, W> {
@@ -169,6 +1063,15 @@ impl<U, M, W> Wren<U, M, W> {
         &mut unsafe { WrenData::associated_init_mut(self.data_ptr()) }.1
     }
 
+    /// Creates a [`Diagnostic`] reporting `error`, with the source code of
+    /// its modules loaded by this virtual machine's module loader.
+    pub fn diagnose(&self, error: &Error) -> Diagnostic
+    where
+        M: ModuleLoader,
+    {
+        Diagnostic::new(error).with_loader(self.loader())
+    }
+
     /// Gets a reference to the output sink of this virtual machine.
     pub fn writer(&self) -> &W {
         // Safety: The associated data remains valid until this `Wren` is dropped.
@@ -189,29 +1092,127 @@ impl<U, M, W> Wren<U, M, W> {
         unsafe { &*self.0.get_user_data::<WrenHeader>() }
     }
 
-    unsafe fn header_mut(&mut self) -> &mut WrenHeader {
-        unsafe { &mut *self.0.get_user_data::<WrenHeader>() }
+    /// Gets the heap of this virtual machine.
+    ///
+    /// A mutable reference to the whole header is never created, as handles
+    /// may access its reference count from other threads.
+    fn heap_mut(&mut self) -> &mut Heap {
+        // Safety: The heap is only accessed by the thread which owns this
+        // `Wren`, or by the thread which frees the virtual machine.
+        unsafe { &mut (*self.header_ptr()).heap }
     }
 }
 
+// Safety: The virtual machine is only ever used through a mutable reference to
+// its `Wren`, or by the last owner to free it. Handles which are dropped on
+// other threads only touch the atomic reference count and the queue of pending
+// releases in the header.
+unsafe impl<U: Send, M: Send, W: Send> Send for Wren<U, M, W> {}
+
 impl<U, M, W> Drop for Wren<U, M, W> {
     fn drop(&mut self) {
         let ptr = self.data_ptr();
 
         unsafe { WrenData::drop_associated(ptr) };
 
-        unsafe { WrenHeader::release(ptr.cast()) };
+        // Fibers parked on tasks can no longer be resumed, and their handles
+        // would otherwise keep the virtual machine alive.
+        unsafe { self.header() }.tasks.clear();
+
+        // Safety: No other reference to the timers exists while this `Wren`
+        // is being dropped.
+        let timers = unsafe { (*self.header_ptr()).timers.clear() };
+        drop(timers);
+
+        // Safety: No code is run after this `Wren` is dropped.
+        unsafe { foreigns::drop_live_objects(self.header_ptr()) };
+
+        // Safety: This `Wren` owns a reference to the virtual machine, and is
+        // not used again.
+        unsafe { WrenHeader::release(self.0) };
     }
 }
 
 /// A compiled identifier for a Wren method signature.
+///
+/// A call handle keeps the underlying virtual machine allocated, but not the
+/// [`Wren`] it was created from. Once that `Wren` is dropped, the handle can
+/// no longer be used, and dropping it frees the virtual machine if it was the
+/// last handle remaining.
+///
+/// Call handles may be sent to and dropped on any thread. The underlying Wren
+/// handle is released the next time its [`Wren`] is used.
 pub struct CallHandle(WrenPtr, HandlePtr);
 
+// Safety: A call handle can only be used through its `Wren`, and dropping it
+// only touches the synchronised parts of the header.
+unsafe impl Send for CallHandle {}
+unsafe impl Sync for CallHandle {}
+
+impl CallHandle {
+    /// Creates a call handle for `signature` in the virtual machine `vm`.
+    ///
+    /// # Panics
+    /// Panics if `signature` contains a NUL byte.
+    pub(crate) fn new(vm: &WrenPtr, signature: &str) -> CallHandle {
+        let signature = CString::new(signature).expect("signatures cannot contain NUL bytes");
+
+        let call_handle = vm.make_call_handle(&signature);
+
+        unsafe { WrenHeader::claim(vm.get_user_data()) };
+
+        CallHandle(*vm, call_handle)
+    }
+}
+
 impl Drop for CallHandle {
     fn drop(&mut self) {
-        let ptr = self.0.get_user_data::<WrenHeader>();
+        // Safety: The virtual machine is kept alive by the reference this
+        // handle owns.
+        unsafe { WrenHeader::defer_release(self.0, self.1) };
 
-        unsafe { WrenHeader::release(ptr) };
+        unsafe { WrenHeader::release(self.0) };
+    }
+}
+
+/// A call handle cached by a type such as [`Fiber`], which is not counted by
+/// [`MemoryStats::handles`].
+///
+/// [`Fiber`]: crate::Fiber
+pub(crate) struct CachedCallHandle(CallHandle);
+
+impl CachedCallHandle {
+    /// Creates a cached call handle for `signature` in the virtual machine
+    /// `vm`.
+    ///
+    /// # Panics
+    /// Panics if `signature` contains a NUL byte.
+    pub fn new(vm: &WrenPtr, signature: &str) -> CachedCallHandle {
+        let call_handle = CallHandle::new(vm, signature);
+
+        let header = vm.get_user_data::<WrenHeader>();
+
+        unsafe { (*header).internal_handles.fetch_add(1, Ordering::Relaxed) };
+
+        CachedCallHandle(call_handle)
+    }
+}
+
+impl Deref for CachedCallHandle {
+    type Target = CallHandle;
+
+    fn deref(&self) -> &CallHandle {
+        &self.0
+    }
+}
+
+impl Drop for CachedCallHandle {
+    fn drop(&mut self) {
+        let header = self.0.0.get_user_data::<WrenHeader>();
+
+        // Safety: The virtual machine is kept alive by the call handle, which
+        // is dropped after this.
+        unsafe { (*header).internal_handles.fetch_sub(1, Ordering::Relaxed) };
     }
 }
 
@@ -222,7 +1223,7 @@ pub(crate) struct WrenData<U, M, W> {
 }
 
 impl<U, M, W> WrenData<U, M, W> {
-    pub fn allocate(user_data: U, loader: M, writer: W) -> *mut WrenData<U, M, W> {
+    pub fn allocate(heap: Heap, user_data: U, loader: M, writer: W) -> *mut WrenData<U, M, W> {
         let layout = Layout::new::<Self>();
 
         let ptr = unsafe { std::alloc::alloc(layout) }.cast::<Self>();
@@ -233,7 +1234,7 @@ impl<U, M, W> WrenData<U, M, W> {
 
         unsafe {
             ptr.write(Self {
-                header: WrenHeader::new(layout),
+                header: WrenHeader::new(layout, heap),
                 associated: MaybeUninit::new((user_data, loader, writer)),
             })
         };
@@ -272,41 +1273,156 @@ impl<U, M, W> WrenData<U, M, W> {
 
 pub(crate) struct WrenHeader {
     pub inner_layout: Layout,
-    pub ref_count: usize,
-    pub foreign_classes: Box<[crate::foreigns::ForeignMethod]>,
+    /// The number of `Wren`s and handles referencing the virtual machine.
+    pub ref_count: AtomicUsize,
+    /// The number of handles counted by `ref_count` which are cached by the
+    /// library, rather than held by the host.
+    pub internal_handles: AtomicUsize,
+    /// Handles which have been dropped, but not yet released by the virtual
+    /// machine.
+    pub pending_release: Mutex<Vec<HandlePtr>>,
+    /// The virtual machine, once it has been created.
+    pub vm: Option<WrenPtr>,
+    pub heap: Heap,
+    pub interrupt: InterruptHandle,
+    pub step_budget: StepBudget,
+    /// Whether scripts may import the optional `meta` module.
+    pub meta: bool,
+    /// Whether scripts may import the optional `random` module.
+    pub random: bool,
+    /// An error raised by a callback, to be returned once control returns to
+    /// the host.
+    pub error: Option<Error>,
+    /// Whether code run by the virtual machine has ever been aborted.
+    pub faulted: bool,
+    /// The compile errors reported by the code currently being run.
+    pub compile_errors: Vec<CompileError>,
+    /// The runtime error reported by the code currently being run, and its
+    /// stack trace.
+    pub runtime_error: RuntimeError,
+    /// The last panic caught in a foreign method, which aborted its fiber.
+    pub panic: Option<Panic>,
+    /// Whether errors are written to the output as they are reported.
+    pub print_errors: bool,
+    /// Whether the next import of the bridge is made by code generated by
+    /// this crate, rather than by a script.
+    pub bridge_import: bool,
+    /// The foreign classes which can be bound by the virtual machine.
+    pub foreign_classes: Vec<ForeignClass>,
+    pub live_objects: LiveObjects,
+    /// The async methods which can be bound by the virtual machine, in the
+    /// order they were registered.
+    pub async_methods: Vec<AsyncMethod>,
+    pub executor: Option<Arc<dyn Executor>>,
+    pub tasks: Arc<Tasks>,
+    pub timers: Timers,
 }
 
 impl WrenHeader {
-    pub fn new(inner_layout: Layout) -> WrenHeader {
+    pub fn new(inner_layout: Layout, heap: Heap) -> WrenHeader {
+        #[cfg(test)]
+        tests::LIVE_VMS.with(|live| live.set(live.get() + 1));
+
         WrenHeader {
             inner_layout,
-            ref_count: 1,
-            foreign_classes: Box::from([]),
+            ref_count: AtomicUsize::new(1),
+            internal_handles: AtomicUsize::new(0),
+            pending_release: Mutex::new(Vec::new()),
+            vm: None,
+            heap,
+            interrupt: InterruptHandle::new(),
+            step_budget: StepBudget::default(),
+            meta: false,
+            random: false,
+            error: None,
+            faulted: false,
+            compile_errors: Vec::new(),
+            runtime_error: RuntimeError::default(),
+            panic: None,
+            print_errors: true,
+            bridge_import: false,
+            foreign_classes: foreigns::builtin_classes(),
+            live_objects: LiveObjects::default(),
+            async_methods: Vec::new(),
+            executor: None,
+            tasks: Arc::default(),
+            timers: Timers::default(),
         }
     }
 
-    pub unsafe fn release(this: *mut WrenHeader) {
-        let ref_count = unsafe { WrenHeader::ref_count(this) };
+    /// Releases a reference to the virtual machine `vm`.
+    ///
+    /// If this was the last reference, the virtual machine is freed, and then
+    /// the header stored in its user data is dropped and deallocated.
+    ///
+    /// # Safety
+    /// - The user data of `vm` must point to a valid `WrenHeader`,
+    /// - the caller must own one of the references counted by that header,
+    /// - `vm` must not be used by the caller after this function returns.
+    pub unsafe fn release(vm: WrenPtr) {
+        let this = vm.get_user_data::<WrenHeader>();
+
+        if unsafe { WrenHeader::decrement_ref_count(this) } != 0 {
+            return;
+        }
 
-        let ref_count = unsafe { ref_count.unchecked_sub(1) };
+        // Synchronise with every other owner's release, so their uses of the
+        // virtual machine happen before it is freed.
+        fence(Ordering::Acquire);
 
-        unsafe { (*this).ref_count = ref_count };
+        // Safety: No references remain, so nothing else can use the virtual
+        // machine. Wren does not release outstanding handles itself.
+        unsafe { WrenHeader::release_pending(vm) };
 
-        if ref_count == 0 {
-            unsafe { std::ptr::drop_in_place(this) }
+        // Freeing the virtual machine may still call back into the allocator,
+        // so the header is deallocated afterwards.
+        unsafe { vm.free() };
 
-            let layout = unsafe { (*this).inner_layout };
+        #[cfg(test)]
+        tests::LIVE_VMS.with(|live| live.set(live.get() - 1));
 
-            unsafe { std::alloc::dealloc(this.cast::<u8>(), layout) };
-        }
+        let layout = unsafe { (*this).inner_layout };
+
+        unsafe { std::ptr::drop_in_place(this) };
+
+        unsafe { std::alloc::dealloc(this.cast::<u8>(), layout) };
     }
 
-    pub unsafe fn claim(this: *mut WrenHeader) {
-        let ref_count = unsafe { WrenHeader::ref_count(this) };
+    /// Queues `handle` to be released by the thread which owns `vm`.
+    ///
+    /// # Safety
+    /// - The user data of `vm` must point to a valid `WrenHeader`,
+    /// - `handle` must have been created by `vm`, and not be used again.
+    pub unsafe fn defer_release(vm: WrenPtr, handle: HandlePtr) {
+        let this = vm.get_user_data::<WrenHeader>();
+
+        let pending = unsafe { &(*this).pending_release };
+
+        pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(handle);
+    }
+
+    /// Releases every handle queued by [`WrenHeader::defer_release`].
+    ///
+    /// # Safety
+    /// - The user data of `vm` must point to a valid `WrenHeader`,
+    /// - the caller must have exclusive use of the virtual machine.
+    pub unsafe fn release_pending(vm: WrenPtr) {
+        let this = vm.get_user_data::<WrenHeader>();
 
-        let ref_count = ref_count.checked_add(1).unwrap();
+        let pending = unsafe { &(*this).pending_release };
 
-        unsafe { (*this).ref_count = ref_count };
+        let handles = std::mem::take(&mut *pending.lock().unwrap_or_else(PoisonError::into_inner));
+
+        for handle in handles {
+            unsafe { vm.release_handle(handle) };
+        }
+    }
+
+    pub unsafe fn claim(this: *mut WrenHeader) {
+        unsafe { WrenHeader::increment_ref_count(this) };
     }
 
     pub unsafe fn deallocate(this: *mut WrenHeader) {
@@ -318,7 +1434,7 @@ impl WrenHeader {
     }
 
     pub unsafe fn ref_count(this: *mut WrenHeader) -> usize {
-        unsafe { (*this).ref_count }
+        unsafe { (*this).ref_count.load(Ordering::Acquire) }
     }
 
     /// Increments the reference count in the given header.
@@ -326,37 +1442,158 @@ impl WrenHeader {
     /// # Safety
     /// - `this` must point to a valid WrenHeader,
     pub unsafe fn increment_ref_count(this: *mut WrenHeader) {
-        let ref_count = unsafe { WrenHeader::ref_count(this) };
+        let ref_count = unsafe { &(*this).ref_count };
 
-        unsafe {
-            (*this).ref_count = ref_count
-                .checked_add(1)
-                .expect("reference count in wren virtual machine overflowed.");
-        }
+        // A new reference can only be created from an existing one, so no
+        // synchronisation is needed, as with `Arc`.
+        ref_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_add(1)
+            })
+            .expect("reference count in wren virtual machine overflowed.");
     }
 
-    /// Decrements the reference count in the given header.
+    /// Decrements the reference count in the given header, returning the new
+    /// count.
     ///
     /// # Safety
     /// - `this` must point to a valid WrenHeader,
     /// - the underlying reference count must be greater than zero.
-    pub unsafe fn decrement_ref_count(this: *mut WrenHeader) {
-        let ref_count = unsafe { WrenHeader::ref_count(this) };
+    pub unsafe fn decrement_ref_count(this: *mut WrenHeader) -> usize {
+        let ref_count = unsafe { &(*this).ref_count };
 
-        unsafe { (*this).ref_count = ref_count.unchecked_sub(1) };
+        ref_count.fetch_sub(1, Ordering::Release) - 1
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{cell::Cell, rc::Rc};
+
     use super::*;
+    use crate::value::{Closure, Handle, Sequence};
+
+    thread_local! {
+        /// The number of headers allocated on this thread whose virtual
+        /// machine has not yet been freed.
+        pub static LIVE_VMS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct DropCounter(Rc<Cell<usize>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
 
     #[test]
     fn allocate_wren_data() {
-        let data = WrenData::allocate((), (), Vec::<u8>::with_capacity(15));
+        let heap = Heap::new(Box::new(std::alloc::System), None);
+
+        let data = WrenData::allocate(heap, (), (), Vec::<u8>::with_capacity(15));
 
         unsafe { WrenData::drop_associated(data) };
 
-        unsafe { WrenHeader::release(data.cast()) };
+        let header = data.cast::<WrenHeader>();
+
+        unsafe { std::ptr::drop_in_place(header) };
+        unsafe { WrenHeader::deallocate(header) };
+
+        LIVE_VMS.with(|live| live.set(live.get() - 1));
+    }
+
+    #[test]
+    fn free_without_handles() {
+        let drops = Rc::new(Cell::new(0));
+
+        let wren = Builder::new().with_data(DropCounter(drops.clone())).build();
+
+        assert_eq!(LIVE_VMS.get(), 1);
+
+        drop(wren);
+
+        assert_eq!(drops.get(), 1);
+        assert_eq!(LIVE_VMS.get(), 0);
+    }
+
+    #[test]
+    fn handles_outlive_wren() {
+        let drops = Rc::new(Cell::new(0));
+
+        let mut wren = Builder::new().with_data(DropCounter(drops.clone())).build();
+
+        wren.interpret("main", "var list = [1, 2, 3]").unwrap();
+
+        let call_handle = wren.make_call_handle("count").unwrap();
+        let handle = wren.get_variable::<Handle>("main", "list").unwrap();
+
+        drop(wren);
+
+        // The user data is dropped with the `Wren`, but the virtual machine
+        // stays allocated until every handle is released.
+        assert_eq!(drops.get(), 1);
+        assert_eq!(LIVE_VMS.get(), 1);
+
+        drop(handle);
+        assert_eq!(LIVE_VMS.get(), 1);
+
+        drop(call_handle);
+        assert_eq!(LIVE_VMS.get(), 0);
+    }
+
+    #[test]
+    fn dropped_handles_are_released() {
+        let mut wren = Wren::new();
+
+        wren.interpret("main", "var list = [1, 2, 3]\nvar n = 1")
+            .unwrap();
+
+        let pending = |wren: &Wren<()>| {
+            unsafe { wren.header() }
+                .pending_release
+                .lock()
+                .unwrap()
+                .len()
+        };
+
+        drop(wren.get_variable::<Handle>("main", "list").unwrap());
+        assert_eq!(pending(&wren), 1);
+        wren.get_variable::<f64>("main", "n").unwrap();
+        assert_eq!(pending(&wren), 0);
+
+        drop(wren.get_variable::<Handle>("main", "list").unwrap());
+        wren.collect_garbage();
+        assert_eq!(pending(&wren), 0);
+
+        drop(wren.get_variable::<Handle>("main", "list").unwrap());
+        wren.memory_stats();
+        assert_eq!(pending(&wren), 0);
+    }
+
+    #[test]
+    fn foreign_values_holding_handles() {
+        let mut wren = Wren::new();
+
+        wren.interpret(
+            "main",
+            "var list = [1, 2, 3]\nvar count = null\nvar lists = null",
+        )
+        .unwrap();
+
+        let list = wren.get_variable::<Handle>("main", "list").unwrap();
+        let count = Closure::new(move || {
+            let _list = &list;
+            3.0
+        });
+        let lists = Sequence::new([wren.get_variable::<Handle>("main", "list").unwrap()]);
+
+        wren.set_variable("main", "count", count).unwrap();
+        wren.set_variable("main", "lists", lists).unwrap();
+
+        // The handles captured by values held by Wren would otherwise keep the
+        // virtual machine allocated.
+        drop(wren);
+        assert_eq!(LIVE_VMS.get(), 0);
     }
 }
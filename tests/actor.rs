@@ -0,0 +1,49 @@
+mod common;
+
+use wrenlet::{AsyncWren, Wren, error::Error, value::OwnedValue};
+
+use common::block_on;
+
+#[test]
+fn requests() {
+    let wren = AsyncWren::spawn(Wren::new());
+
+    let source = r#"
+        class Math {
+            static sum(list) { list.reduce(0) {|a, b| a + b } }
+        }
+        var names = ["a", "b"]
+    "#;
+
+    block_on(wren.interpret("main", source)).unwrap();
+
+    let names = block_on(wren.get_variable("main", "names")).unwrap();
+    let OwnedValue::List(names) = names else {
+        panic!("expected a list, found {names:?}");
+    };
+    assert!(
+        matches!(&names[..], [OwnedValue::String(a), OwnedValue::String(b)] if a == b"a" && b == b"b")
+    );
+
+    let numbers = OwnedValue::List(vec![OwnedValue::Num(1.0), OwnedValue::Num(2.5)]);
+    let sum = block_on(wren.call_method("main", "Math", "sum(_)", vec![numbers])).unwrap();
+    assert!(matches!(sum, OwnedValue::Num(3.5)));
+
+    assert!(matches!(
+        block_on(wren.get_variable("main", "missing")),
+        Err(Error::NoSuchVariable)
+    ));
+}
+
+#[test]
+fn disconnected() {
+    let wren = AsyncWren::spawn(Wren::new());
+
+    let panicked = wren.execute(|_| -> Result<(), Error> { panic!("stop the thread") });
+    assert!(matches!(block_on(panicked), Err(Error::Disconnected)));
+
+    assert!(matches!(
+        block_on(wren.interpret("main", "")),
+        Err(Error::Disconnected)
+    ));
+}
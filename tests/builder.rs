@@ -0,0 +1,235 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use wrenlet::{Fiber, Wren, allocator::WrenAllocator, error::Error, value::Closure};
+
+#[test]
+fn heap_options() {
+    let garbage = r#"
+        for (i in 0...20000) {
+            var item = "item %(i)"
+        }
+    "#;
+
+    // A small heap which grows slowly is collected while the loop runs.
+    let mut small = Wren::builder()
+        .initial_heap_size(64 * 1024)
+        .min_heap_size(16 * 1024)
+        .heap_growth_percent(25)
+        .build();
+
+    small.interpret("main", garbage).unwrap();
+
+    // A large heap is never collected, so every string remains allocated.
+    let mut large = Wren::builder().initial_heap_size(64 * 1024 * 1024).build();
+
+    large.interpret("main", garbage).unwrap();
+
+    let small_peak = small.memory_stats().peak_allocated;
+    let large_peak = large.memory_stats().peak_allocated;
+
+    assert!(
+        small_peak < large_peak / 2,
+        "small heap peaked at {small_peak} bytes, large heap at {large_peak}"
+    );
+
+    let before = large.memory_stats().allocated;
+    large.collect_garbage();
+    let after = large.memory_stats();
+
+    assert!(after.allocated < before);
+    assert_eq!(after.collections, 1);
+}
+
+#[test]
+fn memory_limit() {
+    let mut wren = Wren::builder().with_memory_limit(1024 * 1024).build();
+
+    wren.interpret("main", "var list = []").unwrap();
+
+    let source = r#"
+        for (i in 0...100000) list.add("a string which takes up some space %(i)")
+    "#;
+
+    assert!(matches!(
+        wren.interpret("main", source),
+        Err(Error::OutOfMemory)
+    ));
+
+    // The list is still reachable, so adding to it exceeds the limit again.
+    assert!(matches!(
+        wren.interpret("main", source),
+        Err(Error::OutOfMemory)
+    ));
+}
+
+#[test]
+fn memory_limit_aborts_running_scripts() {
+    let limit = 1024 * 1024;
+    let mut wren = Wren::builder().with_memory_limit(limit).build();
+
+    let tick = Closure::new(|| {});
+
+    wren.interpret("main", "var tick = null").unwrap();
+    wren.set_variable("main", "tick", tick).unwrap();
+
+    // The loop would never end, but it is aborted the next time it calls into
+    // the host once it is over the limit.
+    let source = r#"
+        var list = []
+        var i = 0
+        while (true) {
+            list.add("a string which takes up some space %(i)")
+            tick.call()
+            i = i + 1
+        }
+    "#;
+
+    assert!(matches!(
+        wren.interpret("main", source),
+        Err(Error::OutOfMemory)
+    ));
+    assert!(wren.memory_stats().peak_allocated < 2 * limit);
+}
+
+#[test]
+fn memory_limit_aborts_pure_wren_loops() {
+    let limit = 1024 * 1024;
+    let mut wren = Wren::builder().with_memory_limit(limit).build();
+
+    // The loop never calls into the host, so it is aborted by the interpreter
+    // once it is over the limit.
+    let source = r#"
+        var list = []
+        while (true) list.add("a string which takes up some space %(list.count)")
+    "#;
+
+    assert!(matches!(
+        wren.interpret("main", source),
+        Err(Error::OutOfMemory)
+    ));
+    assert!(wren.memory_stats().peak_allocated < 2 * limit);
+
+    // Garbage which pushes the virtual machine over the limit is collected
+    // instead.
+    let mut wren = Wren::builder().with_memory_limit(limit).build();
+
+    let source = r#"
+        for (i in 0...100000) "a string which takes up some space %(i)"
+    "#;
+
+    wren.interpret("other", source).unwrap();
+}
+
+#[test]
+fn custom_allocator() {
+    struct Counting(Arc<AtomicUsize>);
+
+    unsafe impl WrenAllocator for Counting {
+        unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(1, Ordering::Relaxed);
+
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    let live = Arc::new(AtomicUsize::new(0));
+
+    let mut wren = Wren::builder()
+        .with_allocator(Counting(live.clone()))
+        .build();
+
+    wren.interpret("main", "var list = [1, 2, 3]").unwrap();
+
+    assert_ne!(live.load(Ordering::Relaxed), 0);
+
+    drop(wren);
+
+    assert_eq!(live.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn memory_stats() {
+    let mut wren = Wren::new();
+
+    let before = wren.memory_stats();
+
+    assert_ne!(before.allocated, 0);
+    assert_eq!(before.handles, 0);
+
+    wren.interpret("main", "var list = (0...1000).map {|i| \"%(i)\" }.toList")
+        .unwrap();
+    wren.interpret("main", "var fiber = Fiber.new {}").unwrap();
+
+    let handle = wren.make_call_handle("count").unwrap();
+    let fiber: Fiber = wren.get_variable("main", "fiber").unwrap();
+    let during = wren.memory_stats();
+
+    assert!(during.allocated > before.allocated);
+    assert!(during.peak_allocated >= during.allocated);
+    // The call handles cached by the fiber are not counted.
+    assert_eq!(during.handles, 2);
+
+    drop(handle);
+    drop(fiber);
+    wren.interpret("main", "list = null").unwrap();
+    wren.collect_garbage();
+
+    let after = wren.memory_stats();
+
+    assert!(after.allocated < during.allocated);
+    assert!(after.peak_allocated >= during.peak_allocated);
+    assert_eq!(after.collections, 1);
+    assert_eq!(after.handles, 0);
+
+    // Collections started by Wren itself are counted too.
+    let mut wren = Wren::builder()
+        .initial_heap_size(64 * 1024)
+        .min_heap_size(64 * 1024)
+        .build();
+
+    wren.interpret("main", "for (i in 0...10000) \"%(i)\"")
+        .unwrap();
+
+    assert!(wren.memory_stats().collections > 0);
+}
+
+#[test]
+fn print_errors() {
+    let mut wren = Wren::builder().with_output(Vec::new()).build();
+
+    assert!(wren.interpret("main", "var a = )").is_err());
+    assert!(wren.interpret("main", "Fiber.abort(\"oops\")").is_err());
+
+    // Errors in the code wrapping an expression are returned, but not
+    // printed.
+    assert!(wren.eval::<f64>("main", ")").is_err());
+
+    let output = String::from_utf8(wren.writer().clone()).unwrap();
+    assert_eq!(
+        output,
+        "[main line 1] [Error] Error at ')': Expected expression.\n\
+        [Runtime Error] oops\n\
+        [main line 1] in (script)\n"
+    );
+
+    // Printing can be turned off.
+    let mut wren = Wren::builder()
+        .with_output(Vec::new())
+        .print_errors(false)
+        .build();
+
+    assert!(wren.interpret("main", "var a = )").is_err());
+    assert!(wren.writer().is_empty());
+}
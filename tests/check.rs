@@ -0,0 +1,66 @@
+use wrenlet::Wren;
+
+#[test]
+fn valid_source_is_not_run() {
+    let source = r#"
+        import "missing" for Missing
+        class Main {
+            static run() { Missing.call() }
+        }
+        Fiber.abort("the module was run")
+        while (true) {}
+    "#;
+
+    assert_eq!(Wren::check("main", source), Ok(()));
+}
+
+#[test]
+fn errors_are_reported() {
+    let source = "var a = 1\nvar b = )\nclass {\n}\n";
+
+    let errors = Wren::check("upload", source).unwrap_err();
+
+    assert!(errors.len() >= 2, "{errors:?}");
+    assert!(errors.iter().all(|error| error.module() == "upload"));
+
+    assert_eq!(errors[0].line(), 2);
+    assert!(errors[0].message().contains("Expected expression"));
+
+    assert!(errors.iter().any(|error| error.line() == 3));
+}
+
+#[test]
+fn shebang() {
+    let source = "#!/usr/bin/env wren\nSystem.print(\"never printed\")\n";
+    assert_eq!(Wren::check("main", source), Ok(()));
+
+    assert_eq!(Wren::check("main", "#!/usr/bin/env wren"), Ok(()));
+
+    let errors = Wren::check("main", "#!/usr/bin/env wren\nvar a = 1\nvar b = )").unwrap_err();
+    assert_eq!(errors[0].line(), 3);
+}
+
+#[test]
+fn modules_are_independent() {
+    let mut wren = Wren::new();
+
+    let source = "var a = 1";
+    wren.interpret("main", source).unwrap();
+
+    // The source is not compiled in the scope of the existing module, where
+    // `a` is already defined.
+    assert_eq!(Wren::check("main", source), Ok(()));
+    assert!(Wren::check("main", "System.print(a)").is_err());
+}
+
+#[test]
+fn other_errors_are_reported() {
+    let errors = Wren::check("ma\0in", "var a = )").unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line(), 1);
+    assert!(errors[0].message().contains("NUL"));
+
+    let errors = Wren::check("main", "var a = 1\nvar b = \"\0\"").unwrap_err();
+    assert_eq!(errors[0].line(), 2);
+}
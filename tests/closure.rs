@@ -0,0 +1,130 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use wrenlet::{
+    Wren,
+    error::Error,
+    value::{Closure, OwnedValue},
+};
+
+#[test]
+fn arities() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var zero = null
+        var two = null
+        var eight = null
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let zero = Closure::new(|| "zero");
+    let two = Closure::new(|a: f64, b: String| format!("{b}{a}"));
+    let eight = Closure::new(
+        |a: f64, b: f64, c: f64, d: f64, e: f64, f: f64, g: f64, h: f64| {
+            a + b + c + d + e + f + g + h
+        },
+    );
+
+    assert_eq!(two.arity(), 2);
+
+    wren.set_variable("main", "zero", zero).unwrap();
+    wren.set_variable("main", "two", two).unwrap();
+    wren.set_variable("main", "eight", eight).unwrap();
+
+    let source = r#"
+        var results = [
+            zero.call(),
+            two.call(1, "x"),
+            eight.call(1, 2, 3, 4, 5, 6, 7, 8),
+            two.arity,
+            // Extra arguments are ignored.
+            two.call(2, "y", "z")
+        ]
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let results = wren.get_variable("main", "results").unwrap();
+    let OwnedValue::List(results) = results else {
+        panic!("expected a list, found {results:?}");
+    };
+
+    assert!(matches!(
+        &results[..],
+        [
+            OwnedValue::String(zero),
+            OwnedValue::String(two),
+            OwnedValue::Num(36.0),
+            OwnedValue::Num(2.0),
+            OwnedValue::String(extra),
+        ] if zero == b"zero" && two == b"x1" && extra == b"y2"
+    ));
+}
+
+#[test]
+fn shared_state() {
+    let mut wren = Wren::new();
+
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let counter = {
+        let count = count.clone();
+
+        Closure::new(move |n: f64| {
+            count.fetch_add(n as usize, Ordering::Relaxed);
+        })
+    };
+
+    wren.interpret("main", "var counter = null").unwrap();
+    wren.set_variable("main", "counter", counter).unwrap();
+
+    wren.interpret("main", "(1..4).each(counter)").unwrap();
+
+    assert_eq!(count.load(Ordering::Relaxed), 10);
+
+    // Dropping the virtual machine drops the closure.
+    drop(wren);
+    assert_eq!(Arc::strong_count(&count), 1);
+}
+
+#[test]
+fn invalid_calls() {
+    let mut wren = Wren::new();
+
+    let square = Closure::new(|x: f64| x * x);
+
+    wren.interpret("main", "var square = null").unwrap();
+    wren.set_variable("main", "square", square).unwrap();
+
+    let source = r#"
+        var errors = [
+            Fiber.new { square.call() }.try(),
+            Fiber.new { square.call("2") }.try(),
+            Fiber.new { square.call([2]) }.try()
+        ]
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let errors = wren.get_variable("main", "errors").unwrap();
+    let OwnedValue::List(errors) = errors else {
+        panic!("expected a list, found {errors:?}");
+    };
+
+    assert!(matches!(
+        &errors[..],
+        [
+            OwnedValue::String(few),
+            OwnedValue::String(string),
+            OwnedValue::String(list),
+        ] if few == b"Function expects more arguments."
+            && string == b"Argument 1 has the wrong type."
+            && list == b"Argument 1 has the wrong type."
+    ));
+
+    assert!(matches!(
+        wren.interpret("main", "square.call(null)"),
+        Err(Error::Runtime(_))
+    ));
+}
@@ -0,0 +1,149 @@
+use std::borrow::Cow;
+
+use wrenlet::{Diagnostic, Wren, error::Error, module::ModuleLoader};
+
+struct Modules;
+
+impl ModuleLoader for Modules {
+    fn resolve(&self, _importer: &str, _module: &str) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    fn load(&self, module: &str) -> Option<Cow<'_, str>> {
+        match module {
+            "shapes" => Some(Cow::Borrowed(
+                "class Shape {\n  static area(shape) {\n    return shape.width * 2\n  }\n}\n",
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn compile_errors() {
+    let mut wren = Wren::new();
+
+    let source = "var a = 1\nvar b = )\n";
+    let error = wren.interpret("main", source).unwrap_err();
+
+    let diagnostic = Diagnostic::new(&error).with_source("main", source);
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "\
+error: Expected expression.
+ --> main:2
+  |
+2 | var b = )
+  |         ^
+"
+    );
+
+    // Without the source, only the location is reported.
+    assert_eq!(
+        Diagnostic::new(&error).to_string(),
+        "error: Expected expression.\n --> main:2\n"
+    );
+}
+
+#[test]
+fn runtime_errors() {
+    let mut wren = Wren::builder().with_loader(Modules).build();
+
+    let source = "import \"shapes\" for Shape\n\nShape.area(null)\n";
+    let error = wren.interpret("main", source).unwrap_err();
+
+    let Error::Runtime(runtime) = &error else {
+        panic!("expected a runtime error, found {error:?}");
+    };
+
+    assert_eq!(runtime.message(), "Null does not implement 'width'.");
+
+    let frames: Vec<_> = runtime
+        .stack_trace()
+        .iter()
+        .map(|frame| (frame.module(), frame.line(), frame.function()))
+        .collect();
+    assert_eq!(frames, [("shapes", 3, "area(_)"), ("main", 3, "(script)")]);
+
+    let diagnostic = wren.diagnose(&error).with_source("main", source);
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "\
+error: Null does not implement 'width'.
+ --> shapes:3
+  |
+3 |     return shape.width * 2
+  |     ^^^^^^^^^^^^^^^^^^^^^^
+  |
+  = stack trace:
+    area(_) at shapes:3
+    (script) at main:3
+      3 | Shape.area(null)
+"
+    );
+
+    let ansi = diagnostic.ansi().to_string();
+    assert!(ansi.contains("\x1b[1;31merror\x1b[0m"));
+    assert!(ansi.contains("^^^"));
+}
+
+#[test]
+fn eval_errors() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var x = null").unwrap();
+
+    let Err(Error::Runtime(error)) = wren.eval::<f64>("main", "\nx.foo") else {
+        panic!("expected a runtime error");
+    };
+
+    let frame = &error.stack_trace()[0];
+    assert_eq!((frame.module(), frame.line()), ("main", 2));
+}
+
+#[test]
+fn compiled_function_errors() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "").unwrap();
+
+    let body = "x.foo // Fails for numbers.";
+    let function = wren.compile_function("main", &["x"], body).unwrap();
+
+    let error = function.call::<(), _, _, _>(&mut wren, (1.0,)).unwrap_err();
+
+    let Error::Runtime(runtime) = &error else {
+        panic!("expected a runtime error, found {error:?}");
+    };
+
+    let frame = &runtime.stack_trace()[0];
+    assert_eq!((frame.module(), frame.line()), ("main", 1));
+
+    let diagnostic = wren.diagnose(&error).with_source("main", body);
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "\
+error: Num does not implement 'foo'.
+ --> main:1
+  |
+1 | x.foo // Fails for numbers.
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^
+  |
+  = stack trace:
+    new(_) block argument at main:1
+"
+    );
+}
+
+#[test]
+fn other_errors() {
+    let diagnostic = Diagnostic::new(&Error::NoSuchModule);
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "error: The module has not been loaded.\n"
+    );
+}
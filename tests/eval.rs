@@ -0,0 +1,89 @@
+use wrenlet::{
+    Wren,
+    error::Error,
+    value::{Handle, OwnedValue},
+};
+
+#[test]
+fn expressions() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        class Config {
+            static width { 640 }
+        }
+        var name = "wren"
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert_eq!(wren.eval::<f64>("main", "1 + 2").unwrap(), 3.0);
+    assert_eq!(wren.eval::<f64>("main", "Config.width").unwrap(), 640.0);
+    assert_eq!(wren.eval::<&str>("main", "name").unwrap(), "wren");
+    assert!(
+        wren.eval::<bool>("main", "name.count > 3 ? true : false")
+            .unwrap()
+    );
+
+    let list = wren.eval::<OwnedValue>("main", "[\n  1,\n  2\n]").unwrap();
+    assert!(matches!(
+        list,
+        OwnedValue::List(list) if matches!(&list[..], [OwnedValue::Num(1.0), OwnedValue::Num(2.0)])
+    ));
+
+    let _: Handle = wren.eval("main", "Config").unwrap();
+
+    // Evaluating does not define any variables.
+    assert!(!wren.has_variable("main", "Bridge"));
+}
+
+#[test]
+fn cyclic_lists() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var list = []\nlist.add(list)\nlist.add(list)")
+        .unwrap();
+
+    assert!(matches!(
+        wren.get_variable::<OwnedValue>("main", "list"),
+        Err(Error::NestingTooDeep)
+    ));
+    assert!(matches!(
+        wren.eval::<OwnedValue>("main", "[1, list]"),
+        Err(Error::NestingTooDeep)
+    ));
+
+    // The list can still be held through a handle.
+    let _: Handle = wren.get_variable("main", "list").unwrap();
+}
+
+#[test]
+fn errors() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var x = 1").unwrap();
+
+    assert!(matches!(
+        wren.eval::<f64>("missing", "1"),
+        Err(Error::NoSuchModule)
+    ));
+
+    assert!(matches!(
+        wren.eval::<f64>("main", "x.foo"),
+        Err(Error::Runtime(_))
+    ));
+
+    let Err(Error::Compile(errors)) = wren.eval::<f64>("main", "x +* 2") else {
+        panic!("expected a compile error");
+    };
+
+    assert!(!errors.is_empty());
+    assert_eq!(errors[0].module(), "main");
+    assert_eq!(errors[0].line(), 1);
+    assert!(errors[0].message().contains("Expected expression"));
+
+    let Err(Error::Compile(errors)) = wren.interpret("main", "var a = 1\nvar b = )") else {
+        panic!("expected a compile error");
+    };
+
+    assert_eq!(errors[0].line(), 2);
+}
@@ -0,0 +1,65 @@
+use wrenlet::{Fiber, FiberState, Wren, error::Error, value::Handle, value::OwnedValue};
+
+#[test]
+fn resume_from_function() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var numbers = Fn.new {
+            for (i in 1..3) Fiber.yield(i)
+            return "done"
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let function: Handle = wren.get_variable("main", "numbers").unwrap();
+    let fiber = Fiber::new(&mut wren, &function).unwrap();
+
+    for i in 1..=3 {
+        let state = fiber.resume(&mut wren, ()).unwrap();
+        assert!(matches!(state, FiberState::Yielded(OwnedValue::Num(n)) if n == f64::from(i)));
+    }
+
+    let state = fiber.resume(&mut wren, ()).unwrap();
+    assert!(matches!(state, FiberState::Finished(OwnedValue::String(s)) if s == b"done"));
+    assert!(fiber.is_done(&mut wren).unwrap());
+
+    // A finished fiber cannot be resumed.
+    assert!(matches!(
+        fiber.resume(&mut wren, ()),
+        Err(Error::Runtime(_))
+    ));
+}
+
+#[test]
+fn try_resume() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var fiber = Fiber.new {|value|
+            value = Fiber.yield(value * 2)
+            Fiber.abort("bad %(value)")
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let fiber: Fiber = wren.get_variable("main", "fiber").unwrap();
+
+    let state = fiber.try_resume(&mut wren, 4.0).unwrap();
+    assert!(matches!(state, FiberState::Yielded(OwnedValue::Num(8.0))));
+
+    let state = fiber.try_resume(&mut wren, "input").unwrap();
+    assert!(matches!(state, FiberState::Errored(OwnedValue::String(s)) if s == b"bad input"));
+    assert!(fiber.is_done(&mut wren).unwrap());
+}
+
+#[test]
+fn not_a_function() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var x = 1").unwrap();
+
+    let x: Handle = wren.get_variable("main", "x").unwrap();
+
+    assert!(matches!(Fiber::new(&mut wren, &x), Err(Error::Runtime(_))));
+}
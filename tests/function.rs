@@ -0,0 +1,168 @@
+use wrenlet::{
+    CallHandle, Wren, WrenFn,
+    error::Error,
+    value::{Handle, OwnedValue},
+};
+
+#[test]
+fn stored_callbacks() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        class Events {
+            static on(name, callback) {
+                __handlers = __handlers || {}
+                __handlers[name] = callback
+            }
+            static handler(name) { __handlers[name] }
+        }
+
+        var hits = 0
+        Events.on("hit") {|damage| hits = hits + damage }
+        Events.on("tick") { "ticked" }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let events: Handle = wren.get_variable("main", "Events").unwrap();
+    let handler = wren.make_call_handle("handler(_)").unwrap();
+
+    let hit: Handle = wren.call(&handler, &events, ("hit",)).unwrap();
+    let hit = WrenFn::new(&mut wren, hit).unwrap();
+
+    let tick: Handle = wren.call(&handler, &events, ("tick",)).unwrap();
+    let tick = WrenFn::new(&mut wren, tick).unwrap();
+
+    // The script no longer refers to the callbacks.
+    wren.interpret(
+        "main",
+        "Events.on(\"hit\", null)\nEvents.on(\"tick\", null)",
+    )
+    .unwrap();
+    wren.collect_garbage();
+
+    for _ in 0..3 {
+        hit.call::<f64, _, _, _>(&mut wren, (2.0,)).unwrap();
+    }
+    assert_eq!(wren.get_variable::<f64>("main", "hits").unwrap(), 6.0);
+
+    assert_eq!(tick.arity(), 0);
+    assert_eq!(
+        tick.call::<String, _, _, _>(&mut wren, ()).unwrap(),
+        "ticked"
+    );
+
+    assert!(matches!(
+        tick.call::<(), _, _, _>(&mut wren, (1.0,)),
+        Err(Error::ArityMismatch {
+            expected: 0,
+            found: 1
+        })
+    ));
+}
+
+#[test]
+fn passed_back_to_wren() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var double = Fn.new {|x| x * 2 }
+        class Apply {
+            static apply(function, value) { function.call(value) }
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let double: Handle = wren.get_variable("main", "double").unwrap();
+    let double = WrenFn::new(&mut wren, double).unwrap();
+
+    let apply: Handle = wren.get_variable("main", "Apply").unwrap();
+    let call: CallHandle = wren.make_call_handle("apply(_,_)").unwrap();
+
+    let result: f64 = wren.call(&call, &apply, (&double, 21.0)).unwrap();
+    assert_eq!(result, 42.0);
+}
+
+#[test]
+fn checked() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var list = [1, 2]
+        var failing = Fn.new { Fiber.abort("failed") }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let list: Handle = wren.get_variable("main", "list").unwrap();
+    assert!(matches!(
+        WrenFn::new(&mut wren, list),
+        Err(Error::NotAFunction)
+    ));
+
+    let failing: Handle = wren.get_variable("main", "failing").unwrap();
+    let failing = WrenFn::new(&mut wren, failing).unwrap();
+    assert!(matches!(
+        failing.call::<(), _, _, _>(&mut wren, ()),
+        Err(Error::Runtime(_))
+    ));
+}
+
+#[test]
+fn compiled_functions() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var threshold = 10").unwrap();
+
+    let rule = wren
+        .compile_function("main", &["a", "b"], "a * b > threshold")
+        .unwrap();
+    assert_eq!(rule.arity(), 2);
+
+    let matches = (0..1000)
+        .filter(|&i| {
+            rule.call::<bool, _, _, _>(&mut wren, (f64::from(i), 2.0))
+                .unwrap()
+        })
+        .count();
+    assert_eq!(matches, 994);
+
+    let body = r#"
+        var total = 0
+        for (item in items) total = total + item
+        return total
+    "#;
+    let sum = wren.compile_function("main", &["items"], body).unwrap();
+    let items = OwnedValue::from(vec![1.0, 2.0, 3.0]);
+    let total: f64 = sum.call(&mut wren, (items,)).unwrap();
+    assert_eq!(total, 6.0);
+
+    let constant = wren.compile_function("main", &[], "threshold").unwrap();
+    assert_eq!(constant.call::<f64, _, _, _>(&mut wren, ()).unwrap(), 10.0);
+
+    // A comment at the end of a body does not hide the rest of the function.
+    let commented = wren
+        .compile_function("main", &[], "threshold // The limit.")
+        .unwrap();
+    assert_eq!(commented.call::<f64, _, _, _>(&mut wren, ()).unwrap(), 10.0);
+
+    let empty = wren.compile_function("main", &[], "").unwrap();
+    empty.call::<(), _, _, _>(&mut wren, ()).unwrap();
+}
+
+#[test]
+fn compile_function_errors() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "").unwrap();
+
+    assert!(matches!(
+        wren.compile_function("main", &["a b"], "a"),
+        Err(Error::InvalidIdentifier)
+    ));
+
+    let Err(Error::Compile(errors)) = wren.compile_function("main", &["a"], "var b = a\nreturn )")
+    else {
+        panic!("expected a compile error");
+    };
+    assert!(errors.iter().all(|error| error.line() >= 2));
+    assert_eq!(errors[0].line(), 2);
+}
@@ -0,0 +1,89 @@
+use std::io::{self, Read};
+
+use wrenlet::{Wren, error::Error};
+
+#[test]
+fn files() {
+    let dir = std::env::temp_dir().join(format!("wrenlet-interpret-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("main.wren");
+    std::fs::write(&path, "var answer = 42").unwrap();
+
+    let mut wren = Wren::new();
+    wren.interpret_file(&path).unwrap();
+
+    let module = dir.join("main");
+    let module = module.to_string_lossy();
+    assert_eq!(wren.get_variable::<f64>(&module, "answer").unwrap(), 42.0);
+
+    assert!(matches!(
+        wren.interpret_file(dir.join("missing.wren")),
+        Err(Error::Io(error)) if error.kind() == io::ErrorKind::NotFound
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn readers_and_bytes() {
+    let mut wren = Wren::new();
+
+    wren.interpret_reader("main", &b"var a = 1"[..]).unwrap();
+    assert_eq!(wren.get_variable::<f64>("main", "a").unwrap(), 1.0);
+
+    // Strings in Wren source may hold bytes which are not valid UTF-8.
+    wren.interpret_bytes("main", b"var b = \"\xff\"").unwrap();
+    assert_eq!(wren.get_variable::<&[u8]>("main", "b").unwrap(), b"\xff");
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("failed"))
+        }
+    }
+
+    assert!(matches!(
+        wren.interpret_reader("main", Failing),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn interior_nul() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var a = 1").unwrap();
+
+    assert!(matches!(
+        wren.interpret("main", "var b = 2\0"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.interpret("ma\0in", "var b = 2"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.interpret_bytes("main", b"\0"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.get_variable::<f64>("main", "a\0"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.make_call_handle("call\0"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.eval::<f64>("main", "a\0"),
+        Err(Error::InteriorNul)
+    ));
+
+    assert!(!wren.has_module("main\0"));
+    assert!(!wren.has_variable("main", "a\0"));
+
+    let errors = Wren::check("main", "var a = 1\nvar b = \0").unwrap_err();
+    assert_eq!(errors[0].line(), 2);
+}
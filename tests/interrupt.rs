@@ -0,0 +1,144 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use wrenlet::{InterruptHandle, Wren, error::Error, value::Closure};
+
+#[test]
+fn stale_interrupt_is_discarded() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<InterruptHandle>();
+
+    let mut wren = Wren::new();
+    let handle = wren.interrupt_handle();
+
+    handle.interrupt();
+
+    wren.interpret("main", "var x = 1 + 2").unwrap();
+    assert_eq!(wren.get_variable::<f64>("main", "x").unwrap(), 3.0);
+}
+
+/// Interrupts `wren` repeatedly from another thread while `f` runs, as an
+/// interrupt made before a script starts is discarded.
+fn interrupt_during<T>(wren: &mut Wren<()>, f: impl FnOnce(&mut Wren<()>) -> T) -> T {
+    let handle = wren.interrupt_handle();
+    let done = Arc::new(AtomicBool::new(false));
+
+    let interrupter = thread::spawn({
+        let done = done.clone();
+
+        move || {
+            while !done.load(Ordering::Acquire) {
+                thread::sleep(Duration::from_millis(10));
+                handle.interrupt();
+            }
+        }
+    });
+
+    let result = f(wren);
+
+    done.store(true, Ordering::Release);
+    interrupter.join().unwrap();
+
+    result
+}
+
+#[test]
+fn pure_wren_loop_is_interrupted() {
+    let mut wren = Wren::new();
+
+    let result = interrupt_during(&mut wren, |wren| wren.interpret("main", "while (true) {}"));
+
+    assert!(matches!(result, Err(Error::Interrupted)));
+}
+
+#[test]
+fn interrupt_cannot_be_caught() {
+    let mut wren = Wren::new();
+
+    // The inner fiber is interrupted, but the outer loop catches the error,
+    // and is interrupted in turn.
+    let source = r#"
+        var caught = 0
+        while (true) {
+            Fiber.new {
+                while (true) {}
+            }.try()
+            caught = caught + 1
+        }
+    "#;
+
+    let result = interrupt_during(&mut wren, |wren| wren.interpret("main", source));
+
+    assert!(matches!(result, Err(Error::Interrupted)));
+    assert_eq!(wren.get_variable::<f64>("main", "caught").unwrap(), 0.0);
+}
+
+#[test]
+fn step_budget() {
+    let mut wren = Wren::builder().with_step_budget(10_000).build();
+
+    assert!(matches!(
+        wren.interpret("main", "while (true) {}"),
+        Err(Error::Interrupted)
+    ));
+
+    // Each call starts with the full budget.
+    wren.interpret("main", "var i = 0").unwrap();
+
+    for _ in 0..3 {
+        wren.interpret("main", "i = 0\nwhile (i < 1000) i = i + 1")
+            .unwrap();
+    }
+}
+
+#[test]
+fn running_script_is_interrupted() {
+    let mut wren = Wren::new();
+    let handle = wren.interrupt_handle();
+
+    let tick = Closure::new(|| {});
+
+    wren.interpret("main", "var tick = null\nvar i = 0")
+        .unwrap();
+    wren.set_variable("main", "tick", tick).unwrap();
+
+    let done = Arc::new(AtomicBool::new(false));
+
+    // An interrupt made before the script starts is discarded, so the
+    // interrupt is repeated until the script has stopped.
+    let interrupter = thread::spawn({
+        let done = done.clone();
+
+        move || {
+            while !done.load(Ordering::Acquire) {
+                thread::sleep(Duration::from_millis(10));
+                handle.interrupt();
+            }
+        }
+    });
+
+    // The loop never ends on its own, but calls into the host on every
+    // iteration.
+    let source = r#"
+        while (true) {
+            tick.call()
+            i = i + 1
+        }
+    "#;
+
+    assert!(matches!(
+        wren.interpret("main", source),
+        Err(Error::Interrupted)
+    ));
+
+    done.store(true, Ordering::Release);
+    interrupter.join().unwrap();
+
+    assert!(wren.get_variable::<f64>("main", "i").unwrap() > 0.0);
+}
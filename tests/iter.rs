@@ -0,0 +1,52 @@
+use wrenlet::{Fiber, Wren, error::Error, value::Handle};
+
+#[test]
+fn sequences() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        class Countdown is Sequence {
+            construct new(from) { _from = from }
+            iterate(i) { i == null ? _from : (i > 1 ? i - 1 : false) }
+            iteratorValue(i) { "t-%(i)" }
+        }
+        var list = [1, 2, 3]
+        var countdown = Countdown.new(3)
+        var broken = [1, "two", 3].map {|x| x + 1 }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let list: Handle = wren.get_variable("main", "list").unwrap();
+    let items: Vec<f64> = wren.iter(&list).collect::<Result<_, _>>().unwrap();
+    assert_eq!(items, [1.0, 2.0, 3.0]);
+
+    let countdown: Handle = wren.get_variable("main", "countdown").unwrap();
+    let items: Vec<String> = wren.iter(&countdown).collect::<Result<_, _>>().unwrap();
+    assert_eq!(items, ["t-3", "t-2", "t-1"]);
+
+    // Errors raised while iterating are returned as items.
+    let broken: Handle = wren.get_variable("main", "broken").unwrap();
+    let mut iter = wren.iter::<f64>(&broken);
+    assert!(matches!(iter.next(), Some(Ok(2.0))));
+    assert!(matches!(iter.next(), Some(Err(Error::Runtime(_)))));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn generator_fiber() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        var squares = Fiber.new {
+            for (i in 1..4) Fiber.yield(i * i)
+            return "not yielded"
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let fiber: Fiber = wren.get_variable("main", "squares").unwrap();
+    let items: Vec<f64> = fiber.iter(&mut wren).collect::<Result<_, _>>().unwrap();
+    assert_eq!(items, [1.0, 4.0, 9.0, 16.0]);
+
+    assert_eq!(fiber.iter::<f64, _, _, _>(&mut wren).count(), 0);
+}
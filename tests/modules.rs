@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+
+use wrenlet::{Wren, error::Error, module::ModuleLoader};
+
+struct Prelude;
+
+impl ModuleLoader for Prelude {
+    fn resolve(&self, _importer: &str, module: &str) -> Option<Cow<'_, str>> {
+        module
+            .strip_prefix("./")
+            .map(|module| Cow::Owned(module.to_owned()))
+    }
+
+    fn load(&self, module: &str) -> Option<Cow<'_, str>> {
+        match module {
+            "prelude" => Some(Cow::Borrowed("var greeting = \"hello\"")),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn loader() {
+    let mut wren = Wren::builder().with_loader(Prelude).build();
+
+    wren.interpret(
+        "main",
+        "import \"./prelude\" for greeting\nvar x = greeting",
+    )
+    .unwrap();
+
+    assert!(wren.has_module("prelude"));
+    assert_eq!(wren.get_variable::<&str>("main", "x").unwrap(), "hello");
+
+    assert!(matches!(
+        wren.interpret("main", "import \"missing\""),
+        Err(Error::Runtime(_))
+    ));
+}
+
+#[test]
+fn internal_module_cannot_be_imported() {
+    let mut wren = Wren::new();
+
+    assert!(matches!(
+        wren.interpret("main", "import \"wrenlet\" for Bridge"),
+        Err(Error::Runtime(_))
+    ));
+
+    // Not even from code run on behalf of the host.
+    let source = r#"
+        class Importer {
+            static run() {
+                import "wrenlet" for Bridge
+            }
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert!(matches!(
+        wren.eval::<()>("main", "Importer.run()"),
+        Err(Error::Runtime(_))
+    ));
+
+    // The built in modules still import it.
+    wren.interpret("main", "import \"timer\" for Timer")
+        .unwrap();
+    assert_eq!(wren.eval::<f64>("main", "1 + 2").unwrap(), 3.0);
+}
+
+#[test]
+#[cfg(all(feature = "meta", feature = "random"))]
+fn optional_modules() {
+    let mut wren = Wren::new();
+
+    wren.interpret(
+        "main",
+        "import \"random\" for Random\nvar r = Random.new(1).float()",
+    )
+    .unwrap();
+
+    let r = wren.get_variable::<f64>("main", "r").unwrap();
+    assert!((0.0..1.0).contains(&r));
+
+    let mut wren = Wren::builder().meta(false).random(false).build();
+
+    assert!(matches!(
+        wren.interpret("main", "import \"random\" for Random"),
+        Err(Error::Runtime(_))
+    ));
+    assert!(matches!(
+        wren.interpret("main", "import \"meta\" for Meta"),
+        Err(Error::Runtime(_))
+    ));
+    assert!(matches!(
+        wren.meta_eval("main", "System.print(1)"),
+        Err(Error::NoSuchModule)
+    ));
+}
+
+#[test]
+#[cfg(feature = "meta")]
+fn meta() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var width = 1280\nvar log = []")
+        .unwrap();
+    wren.meta_eval("main", "log.add(width / 2)").unwrap();
+
+    assert_eq!(
+        wren.meta_expression::<f64>("main", "log[0] + width")
+            .unwrap(),
+        1920.0
+    );
+    assert!(!wren.has_variable("main", "Meta"));
+    assert!(matches!(
+        wren.meta_expression::<f64>("main", "1 +"),
+        Err(Error::Runtime(_))
+    ));
+}
@@ -0,0 +1,125 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+use wrenlet::{
+    Wren,
+    error::Error,
+    module::ModuleLoader,
+    value::{Closure, OwnedValue, Sequence},
+};
+
+#[test]
+fn foreign_methods() {
+    let mut wren = Wren::new();
+
+    let fail = Closure::new(|| -> f64 { panic!("closure failed") });
+
+    wren.interpret("main", "var fail = null").unwrap();
+    wren.set_variable("main", "fail", fail).unwrap();
+
+    // The panic aborts the calling fiber with its message.
+    wren.interpret("main", "var error = Fiber.new { fail.call() }.try()")
+        .unwrap();
+    assert!(matches!(
+        wren.get_variable("main", "error").unwrap(),
+        OwnedValue::String(error) if error == b"closure failed"
+    ));
+
+    let Err(Error::Runtime(error)) = wren.interpret("main", "fail.call()") else {
+        panic!("expected a runtime error");
+    };
+
+    assert_eq!(error.message(), "closure failed");
+
+    let panic = error.panic().expect("the error should carry the panic");
+    assert_eq!(panic.message(), "closure failed");
+
+    let payload = panic.take_payload().unwrap();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"closure failed"));
+    assert!(panic.take_payload().is_none());
+
+    // The virtual machine remains usable.
+    assert_eq!(wren.eval::<f64>("main", "1 + 1").unwrap(), 2.0);
+}
+
+#[test]
+fn iterators() {
+    let mut wren = Wren::new();
+
+    let numbers = Sequence::new((0..3).map(|n| {
+        assert!(n < 2, "ran out of numbers");
+        n as f64
+    }));
+
+    wren.interpret("main", "var numbers = null").unwrap();
+    wren.set_variable("main", "numbers", numbers).unwrap();
+
+    let Err(Error::Runtime(error)) = wren.interpret("main", "numbers.toList") else {
+        panic!("expected a runtime error");
+    };
+
+    assert_eq!(error.message(), "ran out of numbers");
+    assert!(error.panic().is_some());
+}
+
+struct Panicking;
+
+impl ModuleLoader for Panicking {
+    fn resolve(&self, _importer: &str, _module: &str) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    fn load(&self, module: &str) -> Option<Cow<'_, str>> {
+        panic!("cannot load {module}")
+    }
+}
+
+#[test]
+fn module_loaders() {
+    let mut wren = Wren::builder().with_loader(Panicking).build();
+
+    let Err(Error::Panic(panic)) = wren.interpret("main", "import \"shapes\"") else {
+        panic!("expected a panic");
+    };
+
+    assert_eq!(panic.message(), "cannot load shapes");
+}
+
+struct Output {
+    text: Vec<u8>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!buf.starts_with(b"secret"), "refused to write");
+
+        self.text.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output() {
+    let mut wren = Wren::builder()
+        .with_output(Output { text: Vec::new() })
+        .build();
+
+    wren.interpret("main", "System.print(\"hello\")").unwrap();
+    assert_eq!(wren.writer().text, b"hello\n");
+
+    let Err(Error::Panic(panic)) = wren.interpret("main", "System.print(\"secret\")") else {
+        panic!("expected a panic");
+    };
+
+    assert_eq!(panic.message(), "refused to write");
+
+    // The newline is written separately, after the text which panicked.
+    wren.interpret("main", "System.print(\"again\")").unwrap();
+    assert_eq!(wren.writer().text, b"hello\n\nagain\n");
+}
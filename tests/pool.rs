@@ -0,0 +1,67 @@
+use wrenlet::{Wren, WrenPool};
+
+#[test]
+fn reuse_and_discard() {
+    let pool = WrenPool::new(Wren::builder).with_warm_up("prelude", "var Greeting = \"Hello\"");
+
+    let mut wren = pool.get().unwrap();
+    wren.interpret("main", "import \"prelude\" for Greeting")
+        .unwrap();
+    assert!(wren.has_variable("prelude", "Greeting"));
+    drop(wren);
+
+    assert_eq!(pool.idle_count(), 1);
+
+    // A virtual machine which ended in a runtime error is not reused.
+    let mut wren = pool.get().unwrap();
+    assert_eq!(pool.idle_count(), 0);
+    assert!(wren.interpret("main", "Fiber.abort(\"oops\")").is_err());
+    drop(wren);
+
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn request_modules() {
+    let pool = WrenPool::new(Wren::builder).with_reset(|wren| wren.collect_garbage());
+
+    let mut wren = pool.get().unwrap();
+    let first = wren.module().to_owned();
+    wren.interpret(&first, "var secret = 0").unwrap();
+    drop(wren);
+
+    assert_eq!(pool.idle_count(), 1);
+
+    // The reused virtual machine runs the next request in a fresh module,
+    // which cannot see the previous one.
+    let mut wren = pool.get().unwrap();
+    let second = wren.module().to_owned();
+    assert_ne!(first, second);
+
+    wren.interpret(&second, "var secret = 1").unwrap();
+    assert_eq!(wren.get_variable::<f64>(&second, "secret").unwrap(), 1.0);
+
+    let source = format!("import \"{first}\" for secret");
+    assert!(wren.interpret(&second, &source).is_err());
+}
+
+#[test]
+fn memory_threshold() {
+    let pool = WrenPool::new(Wren::builder).with_memory_threshold(256 * 1024);
+
+    pool.fill(2).unwrap();
+    assert_eq!(pool.idle_count(), 2);
+
+    let mut wren = pool.get().unwrap();
+    wren.interpret(
+        "main",
+        "var list = (0...20000).map {|i| \"item %(i)\" }.toList",
+    )
+    .unwrap();
+    drop(wren);
+
+    assert_eq!(pool.idle_count(), 1);
+
+    pool.get().unwrap().discard();
+    assert_eq!(pool.idle_count(), 0);
+}
@@ -0,0 +1,135 @@
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
+
+use wrenlet::{Wren, error::Error, module::ModuleLoader};
+
+fn log(wren: &mut Wren<()>) -> String {
+    wren.get_variable::<String>("main", "log").unwrap()
+}
+
+#[test]
+fn timers_wait_for_ticks() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        import "timer" for Timer
+        import "scheduler" for Scheduler
+
+        var log = ""
+
+        Scheduler.add {
+            log = log + "a"
+            Timer.sleep(100)
+            log = log + "c"
+        }
+
+        Scheduler.add {
+            log = log + "b"
+            Timer.sleep(50)
+            log = log + "d"
+        }
+
+        Timer.sleep(200)
+        log = log + "e"
+    "#;
+    wren.interpret("main", source).unwrap();
+    assert_eq!(log(&mut wren), "");
+
+    // Fibers waiting on a timer are not counted as handles held by the host.
+    assert_eq!(wren.memory_stats().handles, 0);
+
+    wren.tick(Duration::ZERO).unwrap();
+    assert_eq!(log(&mut wren), "ab");
+
+    wren.tick(Duration::from_millis(99)).unwrap();
+    assert_eq!(log(&mut wren), "abd");
+
+    // The clock never moves backwards.
+    wren.tick(Duration::from_millis(10)).unwrap();
+    assert_eq!(log(&mut wren), "abd");
+
+    wren.tick(Duration::from_millis(1000)).unwrap();
+    assert_eq!(log(&mut wren), "abdce");
+}
+
+#[test]
+fn run_until_idle() {
+    let mut wren = Wren::new();
+
+    let source = r#"
+        import "timer" for Timer
+        import "scheduler" for Scheduler
+
+        var log = ""
+
+        for (i in 1..3) {
+            Scheduler.add {
+                Timer.sleep(40 - i * 10)
+                log = log + "%(i)"
+            }
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    let start = Instant::now();
+    wren.run_until_idle().unwrap();
+
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(log(&mut wren), "321");
+
+    // Nothing is left to wait for.
+    wren.run_until_idle().unwrap();
+}
+
+#[test]
+fn sleep_errors() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "import \"timer\" for Timer")
+        .unwrap();
+
+    for source in ["Timer.sleep(-1)", "Timer.sleep(\"1\")", "Timer.sleep(1/0)"] {
+        assert!(matches!(
+            wren.interpret("main", source),
+            Err(Error::Runtime(_))
+        ));
+    }
+
+    let source = r#"
+        import "scheduler" for Scheduler
+        Scheduler.add { Fiber.abort("failed") }
+        Scheduler.add {}
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    // The second fiber is resumed by the next tick.
+    assert!(matches!(wren.tick(Duration::ZERO), Err(Error::Runtime(_))));
+    wren.tick(Duration::ZERO).unwrap();
+}
+
+struct Timer;
+
+impl ModuleLoader for Timer {
+    fn resolve(&self, _importer: &str, _module: &str) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    fn load(&self, module: &str) -> Option<Cow<'_, str>> {
+        (module == "timer").then_some(Cow::Borrowed("class Timer { static custom { true } }"))
+    }
+}
+
+#[test]
+fn loader_overrides_modules() {
+    let mut wren = Wren::builder().with_loader(Timer).build();
+
+    let source = r#"
+        import "timer" for Timer
+        var custom = Timer.custom
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert!(wren.get_variable::<bool>("main", "custom").unwrap());
+}
@@ -0,0 +1,79 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use wrenlet::{
+    Wren,
+    value::{OwnedValue, Sequence},
+};
+
+#[test]
+fn iterate_lazily() {
+    let mut wren = Wren::new();
+    let produced = Arc::new(AtomicUsize::new(0));
+
+    let counter = produced.clone();
+    let sequence = Sequence::new((1..).map(move |i| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        f64::from(i)
+    }));
+
+    wren.interpret("main", "var numbers = null").unwrap();
+    wren.set_variable("main", "numbers", sequence).unwrap();
+
+    let source = r#"
+        var evens = []
+        for (n in numbers.where {|n| n % 2 == 0 }) {
+            if (evens.count == 3) break
+            evens.add(n)
+        }
+        var first = evens.map {|n| "%(n)" }.join(",")
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert_eq!(
+        wren.get_variable::<String>("main", "first").unwrap(),
+        "2,4,6"
+    );
+    // The loop finds a fourth even number before it breaks, and nothing more.
+    assert_eq!(produced.load(Ordering::Relaxed), 8);
+}
+
+#[test]
+fn nested_values() {
+    let mut wren = Wren::new();
+
+    let rows = vec![
+        OwnedValue::from(vec!["a", "b"]),
+        OwnedValue::from(true),
+        OwnedValue::Null,
+    ];
+
+    wren.interpret("main", "var rows = null").unwrap();
+    wren.set_variable("main", "rows", Sequence::new(rows))
+        .unwrap();
+    wren.interpret("main", "var text = rows.toList.toString")
+        .unwrap();
+
+    assert_eq!(
+        wren.get_variable::<String>("main", "text").unwrap(),
+        "[[a, b], true, null]"
+    );
+}
+
+#[test]
+fn finalized() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var numbers = null").unwrap();
+    wren.set_variable("main", "numbers", Sequence::new([1.0, 2.0]))
+        .unwrap();
+
+    assert_ne!(wren.memory_stats().foreign_allocated, 0);
+
+    wren.interpret("main", "numbers = null").unwrap();
+    wren.collect_garbage();
+
+    assert_eq!(wren.memory_stats().foreign_allocated, 0);
+}
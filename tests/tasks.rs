@@ -0,0 +1,147 @@
+use std::{thread, time::Duration};
+
+mod common;
+
+use wrenlet::{BoxFuture, Wren, error::Error, value::OwnedValue};
+
+use common::block_on;
+
+/// Runs each future on a thread of its own.
+fn spawn_thread(future: BoxFuture<()>) {
+    thread::spawn(move || block_on(future));
+}
+
+async fn fetch(args: Vec<OwnedValue>) -> Result<f64, String> {
+    thread::sleep(Duration::from_millis(10));
+
+    match &args[..] {
+        [OwnedValue::Num(n)] if *n >= 0.0 => Ok(n * 2.0),
+        [OwnedValue::Num(_)] => Err("negative key".to_owned()),
+        _ => Err("expected a number".to_owned()),
+    }
+}
+
+const DB: &str = r#"
+    class Db {
+        foreign static fetch(key)
+    }
+"#;
+
+#[test]
+fn await_result() {
+    let mut wren = Wren::new();
+
+    wren.set_executor(spawn_thread);
+    wren.register_async_method("main", "Db", "fetch(_)", fetch);
+
+    wren.interpret("main", DB).unwrap();
+
+    let source = r#"
+        var results = []
+        var a = Db.fetch(1)
+        var b = Db.fetch(2)
+        results.add(b.await)
+        results.add(a.await)
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    wren.wait_for_tasks().unwrap();
+
+    let results = wren.get_variable("main", "results").unwrap();
+    let OwnedValue::List(results) = results else {
+        panic!("expected a list, found {results:?}");
+    };
+    assert!(matches!(
+        &results[..],
+        [OwnedValue::Num(4.0), OwnedValue::Num(2.0)]
+    ));
+}
+
+#[test]
+fn concurrent_fibers() {
+    let mut wren = Wren::new();
+
+    wren.set_executor(spawn_thread);
+    wren.register_async_method("main", "Db", "fetch(_)", fetch);
+
+    wren.interpret("main", DB).unwrap();
+
+    let source = r#"
+        var total = 0
+        for (i in 1..3) {
+            Fiber.new { total = total + Db.fetch(i).await }.call()
+        }
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert_eq!(wren.get_variable::<f64>("main", "total").unwrap(), 0.0);
+
+    wren.wait_for_tasks().unwrap();
+
+    assert_eq!(wren.get_variable::<f64>("main", "total").unwrap(), 12.0);
+    assert_eq!(wren.resume_tasks().unwrap(), 0);
+}
+
+#[test]
+fn failed_task_aborts_fiber() {
+    let mut wren = Wren::new();
+
+    wren.set_executor(spawn_thread);
+    wren.register_async_method("main", "Db", "fetch(_)", fetch);
+
+    wren.interpret("main", DB).unwrap();
+
+    let source = r#"
+        var error = null
+        Fiber.new {
+            error = Fiber.new { Db.fetch(-1).await }.try()
+        }.call()
+    "#;
+    wren.interpret("main", source).unwrap();
+    wren.wait_for_tasks().unwrap();
+
+    assert_eq!(
+        wren.get_variable::<String>("main", "error").unwrap(),
+        "negative key"
+    );
+
+    wren.interpret("main", "Db.fetch(-1).await").unwrap();
+    assert!(matches!(wren.wait_for_tasks(), Err(Error::Runtime(_))));
+}
+
+#[test]
+fn missing_executor() {
+    let mut wren = Wren::new();
+
+    wren.register_async_method("main", "Db", "fetch(_)", fetch);
+
+    wren.interpret("main", DB).unwrap();
+
+    assert!(matches!(
+        wren.interpret("main", "Db.fetch(1)"),
+        Err(Error::Runtime(_))
+    ));
+}
+
+#[test]
+fn awaited_twice() {
+    let mut wren = Wren::new();
+
+    wren.set_executor(spawn_thread);
+    wren.register_async_method("main", "Db", "fetch(_)", fetch);
+
+    wren.interpret("main", DB).unwrap();
+
+    let source = r#"
+        var task = Db.fetch(1)
+        Fiber.new { task.await }.call()
+    "#;
+    wren.interpret("main", source).unwrap();
+
+    assert!(matches!(
+        wren.interpret("main", "task.await"),
+        Err(Error::Runtime(_))
+    ));
+
+    wren.wait_for_tasks().unwrap();
+}
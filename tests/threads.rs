@@ -0,0 +1,38 @@
+use std::thread;
+
+use wrenlet::Wren;
+
+#[test]
+fn wren_is_send() {
+    fn assert_send<T: Send>() {}
+
+    assert_send::<Wren<()>>();
+    assert_send::<wrenlet::CallHandle>();
+    assert_send::<wrenlet::value::Handle>();
+}
+
+#[test]
+fn move_between_threads() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var list = [1, 2, 3]").unwrap();
+
+    let list: wrenlet::value::Handle = wren.get_variable("main", "list").unwrap();
+    let count = wren.make_call_handle("count").unwrap();
+
+    let worker = thread::spawn(move || {
+        wren.interpret("main", "list.add(4)").unwrap();
+
+        wren
+    });
+
+    // Handles may be dropped while their virtual machine is used elsewhere.
+    drop(list);
+    drop(count);
+
+    let mut wren = worker.join().unwrap();
+
+    assert_eq!(wren.eval::<f64>("main", "list.count").unwrap(), 4.0);
+
+    assert_eq!(wren.memory_stats().handles, 0);
+}
@@ -0,0 +1,109 @@
+use wrenlet::{Wren, error::Error};
+
+#[test]
+fn get_missing_variable() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var answer = 42").unwrap();
+
+    assert!(wren.has_module("main"));
+    assert!(!wren.has_module("other"));
+    assert!(wren.has_variable("main", "answer"));
+    assert!(!wren.has_variable("main", "question"));
+    assert!(!wren.has_variable("other", "answer"));
+
+    assert_eq!(wren.get_variable::<f64>("main", "answer").unwrap(), 42.0);
+    assert!(matches!(
+        wren.get_variable::<f64>("main", "question"),
+        Err(Error::NoSuchVariable)
+    ));
+    assert!(matches!(
+        wren.get_variable::<f64>("other", "answer"),
+        Err(Error::NoSuchModule)
+    ));
+}
+
+#[test]
+fn set_and_define_variables() {
+    let mut wren = Wren::new();
+
+    wren.define_module("config", [("width", 1280.0), ("height", 720.0)])
+        .unwrap();
+    wren.interpret(
+        "main",
+        "import \"config\" for width, height\nvar area = width * height",
+    )
+    .unwrap();
+
+    assert_eq!(wren.get_variable::<f64>("main", "area").unwrap(), 921600.0);
+
+    wren.set_variable("config", "width", 640.0).unwrap();
+    wren.set_variable("config", "title", "game").unwrap();
+
+    assert_eq!(wren.get_variable::<f64>("config", "width").unwrap(), 640.0);
+    assert_eq!(
+        wren.get_variable::<String>("config", "title").unwrap(),
+        "game"
+    );
+    assert!(!wren.has_variable("config", "Bridge"));
+
+    assert!(matches!(
+        wren.set_variable("missing", "x", true),
+        Err(Error::NoSuchModule)
+    ));
+    assert!(matches!(
+        wren.set_variable("config", "x = 1", true),
+        Err(Error::InvalidIdentifier)
+    ));
+    assert!(matches!(
+        wren.set_variable("config", "class", true),
+        Err(Error::InvalidIdentifier)
+    ));
+}
+
+#[test]
+fn names_containing_nul() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var a = 1").unwrap();
+
+    assert!(matches!(
+        wren.get_variable::<f64>("ma\0in", "a"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.set_variable("ma\0in", "a", 2.0),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.set_variable("main", "a\0", 2.0),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.define_module("ma\0in", [("a", 2.0)]),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.eval::<f64>("ma\0in", "a"),
+        Err(Error::InteriorNul)
+    ));
+    assert!(matches!(
+        wren.compile_function("ma\0in", &[], "a"),
+        Err(Error::InteriorNul)
+    ));
+}
+
+#[test]
+fn variables_named_like_the_bridge() {
+    let mut wren = Wren::new();
+
+    wren.interpret("main", "var Bridge = 1").unwrap();
+
+    wren.set_variable("main", "Bridge", 2.0).unwrap();
+    assert_eq!(wren.get_variable::<f64>("main", "Bridge").unwrap(), 2.0);
+
+    assert_eq!(wren.eval::<f64>("main", "Bridge").unwrap(), 2.0);
+
+    let function = wren.compile_function("main", &[], "Bridge").unwrap();
+    assert_eq!(function.call::<f64, _, _, _>(&mut wren, ()).unwrap(), 2.0);
+}